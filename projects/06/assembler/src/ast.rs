@@ -0,0 +1,77 @@
+use crate::code::{Comp, Dest, Jump};
+use std::fmt;
+use std::ops::Range;
+
+/// The byte range of the source line.
+pub type Span = Range<usize>;
+
+/// The value loaded by an A-instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Constant(u16),
+    /// A label, a variable or a constant defined by `.equ`.
+    Symbol(String),
+    /// An expression such as `SCREEN+32` which is evaluated after the symbols are defined.
+    Expression(String),
+}
+
+/// An instruction parsed from a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Address {
+        value: Value,
+        /// The span of the value.
+        span: Span,
+    },
+    Compute {
+        dest: Option<Dest>,
+        comp: Comp,
+        jump: Option<Jump>,
+        span: Span,
+    },
+    Label {
+        name: String,
+        /// The span of the name.
+        span: Span,
+    },
+}
+
+impl Instruction {
+    pub fn span(&self) -> &Span {
+        match self {
+            Instruction::Address { span, .. }
+            | Instruction::Compute { span, .. }
+            | Instruction::Label { span, .. } => span,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Constant(n) => write!(f, "{}", n),
+            Value::Symbol(s) | Value::Expression(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Address { value, .. } => write!(f, "@{}", value),
+            Instruction::Compute {
+                dest, comp, jump, ..
+            } => {
+                if let Some(dest) = dest {
+                    write!(f, "{}=", dest)?;
+                }
+                write!(f, "{}", comp)?;
+                if let Some(jump) = jump {
+                    write!(f, ";{}", jump)?;
+                }
+                Ok(())
+            }
+            Instruction::Label { name, .. } => write!(f, "({})", name),
+        }
+    }
+}
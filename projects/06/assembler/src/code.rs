@@ -1,64 +1,334 @@
-pub fn dest(mnemonic: Option<String>) -> String {
-    match mnemonic.as_deref() {
-        None => "000",
-        Some("M") => "001",
-        Some("D") => "010",
-        Some("MD") => "011",
-        Some("A") => "100",
-        Some("AM") => "101",
-        Some("AD") => "110",
-        Some("AMD") => "111",
-        Some(m) => panic!("unexpected mnemonic was given: {:?}", m),
-    }
-    .to_string()
-}
-
-pub fn comp(mnemonic: String) -> String {
-    match mnemonic.as_str() {
-        "0" => "0101010",
-        "1" => "0111111",
-        "-1" => "0111010",
-        "D" => "0001100",
-        "A" => "0110000",
-        "M" => "1110000",
-        "!D" => "0001101",
-        "!A" => "0110001",
-        "!M" => "1110001",
-        "-D" => "0001111",
-        "-A" => "0110011",
-        "-M" => "1110011",
-        "D+1" => "0011111",
-        "A+1" => "0110111",
-        "M+1" => "1110111",
-        "D-1" => "0001110",
-        "A-1" => "0110010",
-        "D+A" => "0000010",
-        "D-A" => "0010011",
-        "A-D" => "0000111",
-        "D&A" => "0000000",
-        "D|A" => "0010101",
-        "M-1" => "1110010",
-        "D+M" => "1000010",
-        "D-M" => "1010011",
-        "M-D" => "1000111",
-        "D&M" => "1000000",
-        "D|M" => "1010101",
-        _ => panic!("unexpected mnemonic was given: {:?}", mnemonic),
-    }
-    .to_string()
-}
-
-pub fn jump(mnemonic: Option<String>) -> String {
-    match mnemonic.as_deref() {
-        None => "000",
-        Some("JGT") => "001",
-        Some("JEQ") => "010",
-        Some("JGE") => "011",
-        Some("JLT") => "100",
-        Some("JNE") => "101",
-        Some("JLE") => "110",
-        Some("JMP") => "111",
-        Some(m) => panic!("unexpected mnemonic was given: {:?}", m),
-    }
-    .to_string()
+use std::fmt;
+
+/// The registers to store the result of a C-instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dest {
+    M,
+    D,
+    Md,
+    A,
+    Am,
+    Ad,
+    Amd,
+}
+
+/// The computation of a C-instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comp {
+    Zero,
+    One,
+    MinusOne,
+    D,
+    A,
+    M,
+    NotD,
+    NotA,
+    NotM,
+    MinusD,
+    MinusA,
+    MinusM,
+    DPlusOne,
+    APlusOne,
+    MPlusOne,
+    DMinusOne,
+    AMinusOne,
+    MMinusOne,
+    DPlusA,
+    DMinusA,
+    AMinusD,
+    DAndA,
+    DOrA,
+    DPlusM,
+    DMinusM,
+    MMinusD,
+    DAndM,
+    DOrM,
+}
+
+/// The jump condition of a C-instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    Jgt,
+    Jeq,
+    Jge,
+    Jlt,
+    Jne,
+    Jle,
+    Jmp,
+}
+
+const DEST_TABLE: [(Dest, &str, u16); 7] = [
+    (Dest::M, "M", 0b001),
+    (Dest::D, "D", 0b010),
+    (Dest::Md, "MD", 0b011),
+    (Dest::A, "A", 0b100),
+    (Dest::Am, "AM", 0b101),
+    (Dest::Ad, "AD", 0b110),
+    (Dest::Amd, "AMD", 0b111),
+];
+
+const COMP_TABLE: [(Comp, &str, u16); 28] = [
+    (Comp::Zero, "0", 0b0101010),
+    (Comp::One, "1", 0b0111111),
+    (Comp::MinusOne, "-1", 0b0111010),
+    (Comp::D, "D", 0b0001100),
+    (Comp::A, "A", 0b0110000),
+    (Comp::M, "M", 0b1110000),
+    (Comp::NotD, "!D", 0b0001101),
+    (Comp::NotA, "!A", 0b0110001),
+    (Comp::NotM, "!M", 0b1110001),
+    (Comp::MinusD, "-D", 0b0001111),
+    (Comp::MinusA, "-A", 0b0110011),
+    (Comp::MinusM, "-M", 0b1110011),
+    (Comp::DPlusOne, "D+1", 0b0011111),
+    (Comp::APlusOne, "A+1", 0b0110111),
+    (Comp::MPlusOne, "M+1", 0b1110111),
+    (Comp::DMinusOne, "D-1", 0b0001110),
+    (Comp::AMinusOne, "A-1", 0b0110010),
+    (Comp::DPlusA, "D+A", 0b0000010),
+    (Comp::DMinusA, "D-A", 0b0010011),
+    (Comp::AMinusD, "A-D", 0b0000111),
+    (Comp::DAndA, "D&A", 0b0000000),
+    (Comp::DOrA, "D|A", 0b0010101),
+    (Comp::MMinusOne, "M-1", 0b1110010),
+    (Comp::DPlusM, "D+M", 0b1000010),
+    (Comp::DMinusM, "D-M", 0b1010011),
+    (Comp::MMinusD, "M-D", 0b1000111),
+    (Comp::DAndM, "D&M", 0b1000000),
+    (Comp::DOrM, "D|M", 0b1010101),
+];
+
+const JUMP_TABLE: [(Jump, &str, u16); 7] = [
+    (Jump::Jgt, "JGT", 0b001),
+    (Jump::Jeq, "JEQ", 0b010),
+    (Jump::Jge, "JGE", 0b011),
+    (Jump::Jlt, "JLT", 0b100),
+    (Jump::Jne, "JNE", 0b101),
+    (Jump::Jle, "JLE", 0b110),
+    (Jump::Jmp, "JMP", 0b111),
+];
+
+/// Equivalent spellings of comp which are not covered by commuting the operands.
+const COMP_SYNONYMS: [(&str, &str); 11] = [
+    ("0-D", "-D"),
+    ("0-A", "-A"),
+    ("0-M", "-M"),
+    ("D+0", "D"),
+    ("A+0", "A"),
+    ("M+0", "M"),
+    ("D-0", "D"),
+    ("A-0", "A"),
+    ("M-0", "M"),
+    ("-0", "0"),
+    ("!0", "-1"),
+];
+
+fn find_mnemonic<T: Copy>(table: &[(T, &str, u16)], mnemonic: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(_, m, _)| *m == mnemonic)
+        .map(|(t, _, _)| *t)
+}
+
+fn find_bits<T: Copy>(table: &[(T, &str, u16)], bits: u16) -> Option<T> {
+    table
+        .iter()
+        .find(|(_, _, b)| *b == bits)
+        .map(|(t, _, _)| *t)
+}
+
+fn find_entry<T: Copy + PartialEq>(table: &[(T, &'static str, u16)], t: T) -> (&'static str, u16) {
+    table
+        .iter()
+        .find(|(e, _, _)| *e == t)
+        .map(|(_, m, b)| (*m, *b))
+        .unwrap()
+}
+
+impl Dest {
+    /// The destinations may be written in any order such as `DM` or `MA`.
+    pub fn parse(mnemonic: &str) -> Option<Self> {
+        find_mnemonic(&DEST_TABLE, &normalize_dest(mnemonic)?)
+    }
+
+    /// `None` means the instruction stores nothing.
+    pub fn from_bits(bits: u16) -> Option<Self> {
+        find_bits(&DEST_TABLE, bits)
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        find_entry(&DEST_TABLE, self).0
+    }
+
+    pub fn bits(self) -> u16 {
+        find_entry(&DEST_TABLE, self).1
+    }
+}
+
+impl Comp {
+    /// Commuted operations such as `A+D` and `M&D` and the synonyms such as `0-D` are accepted.
+    pub fn parse(mnemonic: &str) -> Option<Self> {
+        find_mnemonic(&COMP_TABLE, &normalize_comp(mnemonic)?)
+    }
+
+    /// The 7 bits including the a-bit.
+    pub fn from_bits(bits: u16) -> Option<Self> {
+        find_bits(&COMP_TABLE, bits)
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        find_entry(&COMP_TABLE, self).0
+    }
+
+    pub fn bits(self) -> u16 {
+        find_entry(&COMP_TABLE, self).1
+    }
+}
+
+impl Jump {
+    pub fn parse(mnemonic: &str) -> Option<Self> {
+        find_mnemonic(&JUMP_TABLE, mnemonic)
+    }
+
+    /// `None` means the instruction never jumps.
+    pub fn from_bits(bits: u16) -> Option<Self> {
+        find_bits(&JUMP_TABLE, bits)
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        find_entry(&JUMP_TABLE, self).0
+    }
+
+    pub fn bits(self) -> u16 {
+        find_entry(&JUMP_TABLE, self).1
+    }
+}
+
+impl fmt::Display for Dest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+impl fmt::Display for Comp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+impl fmt::Display for Jump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+/// Encode a C-instruction.
+pub fn encode(dest: Option<Dest>, comp: Comp, jump: Option<Jump>) -> u16 {
+    0b111 << 13 | comp.bits() << 6 | dest.map_or(0, Dest::bits) << 3 | jump.map_or(0, Jump::bits)
+}
+
+/// Suggest an encodable comp for the one using both A and M, such as `D+M` for `A+M`.
+/// The ALU takes D and either A or M, so one of them is replaced with D.
+pub fn suggest_comp(mnemonic: &str) -> Option<String> {
+    if !mnemonic.contains('A') || !mnemonic.contains('M') {
+        return None;
+    }
+
+    ['A', 'M']
+        .iter()
+        .map(|c| mnemonic.replacen(*c, "D", 1))
+        .find(|m| Comp::parse(m).is_some())
+}
+
+/// Sort the destinations in the canonical order, A, M and D.
+fn normalize_dest(mnemonic: &str) -> Option<String> {
+    let mut registers: Vec<char> = mnemonic.chars().collect();
+    registers.sort_by_key(|c| "AMD".find(*c));
+    registers.dedup();
+    if registers.len() != mnemonic.len() || registers.iter().any(|c| !"AMD".contains(*c)) {
+        return None;
+    }
+
+    Some(registers.into_iter().collect())
+}
+
+/// Rewrite the comp into the spelling in `COMP_TABLE` if possible.
+fn normalize_comp(mnemonic: &str) -> Option<String> {
+    let is_canonical = |m: &str| COMP_TABLE.iter().any(|(_, c, _)| *c == m);
+    if is_canonical(mnemonic) {
+        return Some(mnemonic.to_string());
+    }
+    if let Some((_, canonical)) = COMP_SYNONYMS.iter().find(|(m, _)| *m == mnemonic) {
+        return Some(canonical.to_string());
+    }
+
+    let i = mnemonic.find(|c| "+&|".contains(c)).filter(|i| *i > 0)?;
+    let (lhs, rhs) = (&mnemonic[..i], &mnemonic[i + 1..]);
+    let commuted = format!("{}{}{}", rhs, &mnemonic[i..i + 1], lhs);
+    if is_canonical(&commuted) {
+        return Some(commuted);
+    }
+    COMP_SYNONYMS
+        .iter()
+        .find(|(m, _)| *m == commuted)
+        .map(|(_, canonical)| canonical.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dest_test() {
+        assert_eq!(Some(Dest::Md), Dest::parse("MD"));
+        assert_eq!(Some(Dest::Md), Dest::parse("DM"));
+        assert_eq!(Some(Dest::Amd), Dest::parse("MDA"));
+        assert_eq!(None, Dest::parse("MM"));
+        assert_eq!(None, Dest::parse("X"));
+        assert_eq!(0b011, Dest::Md.bits());
+        assert_eq!("AMD", Dest::Amd.to_string());
+        assert_eq!(None, Dest::from_bits(0));
+    }
+
+    #[test]
+    fn comp_test() {
+        let comp = Comp::parse;
+        assert_eq!(Some(Comp::DPlusA), comp("A+D"));
+        assert_eq!(Some(Comp::DPlusM), comp("M+D"));
+        assert_eq!(Some(Comp::DAndA), comp("A&D"));
+        assert_eq!(Some(Comp::DAndM), comp("M&D"));
+        assert_eq!(Some(Comp::DOrA), comp("A|D"));
+        assert_eq!(Some(Comp::DOrM), comp("M|D"));
+        assert_eq!(Some(Comp::DPlusOne), comp("1+D"));
+        assert_eq!(Some(Comp::MPlusOne), comp("1+M"));
+        assert_eq!(Some(Comp::MinusD), comp("0-D"));
+        assert_eq!(Some(Comp::M), comp("0+M"));
+        assert_eq!(Some(Comp::MinusOne), comp("!0"));
+
+        // Subtraction is not commutative.
+        assert_eq!(None, comp("1-D"));
+        assert_eq!(None, comp("A+M"));
+        assert_eq!(None, comp("+D"));
+
+        assert_eq!(0b1110111, Comp::MPlusOne.bits());
+        assert_eq!(Some(Comp::MPlusOne), Comp::from_bits(0b1110111));
+        assert_eq!("D|M", Comp::DOrM.to_string());
+    }
+
+    #[test]
+    fn encode_test() {
+        assert_eq!(0xEA87, encode(None, Comp::Zero, Some(Jump::Jmp)));
+        assert_eq!(0xFDC8, encode(Some(Dest::M), Comp::MPlusOne, None));
+        assert_eq!(Some(Jump::Jle), Jump::parse("JLE"));
+        assert_eq!(None, Jump::parse("JMQ"));
+    }
+
+    #[test]
+    fn suggest_comp_test() {
+        assert_eq!(Some("D+M".to_string()), suggest_comp("A+M"));
+        assert_eq!(Some("M+D".to_string()), suggest_comp("M+A"));
+        assert_eq!(Some("M-D".to_string()), suggest_comp("M-A"));
+        assert_eq!(Some("D&M".to_string()), suggest_comp("A&M"));
+        assert_eq!(None, suggest_comp("D+X"));
+    }
 }
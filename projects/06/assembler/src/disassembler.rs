@@ -0,0 +1,307 @@
+use crate::code::{Comp, Dest, Jump};
+use crate::error::{AssembleError, ErrorKind};
+use crate::symbol_table::{Address, PREDEFINED_SYMBOLS};
+use std::collections::BTreeSet;
+use std::io::prelude::*;
+
+const INDENT: &str = "    ";
+
+/// How the instruction after an A-instruction uses the value in A.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// The instruction may jump to the value.
+    pub jumps: bool,
+    /// The instruction reads or writes the memory at the value.
+    pub accesses_memory: bool,
+}
+
+impl Usage {
+    /// The usage by the next word, if it is a C-instruction.
+    pub fn of(next: Option<u16>) -> Self {
+        match next {
+            Some(next) if !is_address(next) => Usage {
+                jumps: has_jump(next),
+                // a-bit or the M destination bit.
+                accesses_memory: next & 0x1000 != 0 || next & 0b1000 != 0,
+            },
+            _ => Usage::default(),
+        }
+    }
+}
+
+/// Convert the Hack machine codes into the Hack assembly which can be assembled again.
+pub fn disassemble<R: BufRead, W: Write>(
+    src: &mut R,
+    dst: &mut W,
+) -> Result<(), Vec<AssembleError>> {
+    let (words, lines_of_words) = read_words(src)?;
+    let labels = find_jump_targets(&words);
+
+    let mut errors = Vec::new();
+    let mut lines = Vec::with_capacity(words.len() + labels.len());
+    for (address, word) in words.iter().enumerate() {
+        if labels.contains(&(address as Address)) {
+            lines.push(format!("({})", label_name(address as Address)));
+        }
+
+        let next = words.get(address + 1).cloned();
+        let name = |value, usage| Some(address_operand(value, usage, &labels));
+        match decode(*word, next, name) {
+            Some(instruction) => lines.push(format!("{}{}", INDENT, instruction)),
+            None => {
+                let line = lines_of_words[address];
+                errors.push(word_error(line, *word, ErrorKind::InvalidInstruction))
+            }
+        }
+    }
+
+    // A label may point just after the last instruction.
+    if labels.contains(&(words.len() as Address)) {
+        lines.push(format!("({})", label_name(words.len() as Address)));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    for line in lines {
+        writeln!(dst, "{}", line).map_err(|e| vec![word_error(0, 0, ErrorKind::Io(e))])?;
+    }
+
+    Ok(())
+}
+
+/// Read the words and their 1-origin line numbers, skipping blank lines.
+fn read_words<R: BufRead>(src: &mut R) -> Result<(Vec<u16>, Vec<usize>), Vec<AssembleError>> {
+    let mut words = Vec::new();
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let line = line.map_err(|e| vec![word_error(i + 1, 0, ErrorKind::Io(e))])?;
+        let text = line.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        match u16::from_str_radix(text, 2) {
+            Ok(word) if text.len() == 16 => {
+                words.push(word);
+                lines.push(i + 1);
+            }
+            _ => errors.push(AssembleError {
+                file: String::new(),
+                line: i + 1,
+                column: line.find(text).unwrap_or(0) + 1,
+                text: text.to_string(),
+                source: line.clone(),
+                kind: ErrorKind::InvalidInstruction,
+            }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok((words, lines))
+    } else {
+        Err(errors)
+    }
+}
+
+/// An error of the word at the 1-origin line, or of no line if the line is 0.
+fn word_error(line: usize, word: u16, kind: ErrorKind) -> AssembleError {
+    let source = format!("{:016b}", word);
+    AssembleError {
+        file: String::new(),
+        line,
+        column: 1,
+        text: source.clone(),
+        source,
+        kind,
+    }
+}
+
+fn is_address(word: u16) -> bool {
+    word & 0x8000 == 0
+}
+
+fn has_jump(word: u16) -> bool {
+    !is_address(word) && word & 0b111 != 0
+}
+
+/// Collect the addresses loaded into A just before jump instructions.
+fn find_jump_targets(words: &[u16]) -> BTreeSet<Address> {
+    words
+        .windows(2)
+        .filter(|w| is_address(w[0]) && has_jump(w[1]))
+        .map(|w| w[0])
+        .filter(|target| *target as usize <= words.len())
+        .collect()
+}
+
+fn label_name(address: Address) -> String {
+    format!("L_{:04X}", address)
+}
+
+/// Decode a word into an instruction, or `None` if it is not a valid instruction.
+///
+/// The value of an A-instruction is named by `name` with the usage by the next word, or written
+/// as a number if `name` returns `None`.
+pub fn decode<F>(word: u16, next: Option<u16>, name: F) -> Option<String>
+where
+    F: FnOnce(u16, Usage) -> Option<String>,
+{
+    if is_address(word) {
+        let operand = name(word, Usage::of(next)).unwrap_or_else(|| word.to_string());
+        return Some(format!("@{}", operand));
+    }
+
+    // Both bits are always 1 in C-instructions.
+    if word & 0x6000 != 0x6000 {
+        return None;
+    }
+
+    let comp = Comp::from_bits((word >> 6) & 0x7F)?;
+    let dest = Dest::from_bits((word >> 3) & 0b111);
+    let jump = Jump::from_bits(word & 0b111);
+
+    let mut instruction = String::new();
+    if let Some(dest) = dest {
+        instruction.push_str(dest.mnemonic());
+        instruction.push('=');
+    }
+    instruction.push_str(comp.mnemonic());
+    if let Some(jump) = jump {
+        instruction.push(';');
+        instruction.push_str(jump.mnemonic());
+    }
+
+    Some(instruction)
+}
+
+/// Render the value of an A-instruction.
+///
+/// Jump targets are rendered as the synthesized labels.
+/// SCREEN and KBD are always rendered symbolically, while the registers (0-15) are rendered
+/// symbolically only if the next instruction accesses the memory through them.
+fn address_operand(value: u16, usage: Usage, labels: &BTreeSet<Address>) -> String {
+    if usage.jumps && labels.contains(&value) {
+        return label_name(value);
+    }
+
+    PREDEFINED_SYMBOLS
+        .iter()
+        .find(|(_, address)| *address == value && (usage.accesses_memory || *address > 15))
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::str;
+
+    fn disassemble_str(input: &str) -> Result<String, Vec<AssembleError>> {
+        let mut input = Cursor::new(input);
+        let mut output = Vec::<u8>::new();
+        disassemble(&mut input, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn disassemble_test() {
+        let input = "0000000000000000\n\
+                     1111110000010000\n\
+                     0000000000000001\n\
+                     1111010011010000\n\
+                     0000000000001010\n\
+                     1110001100000001\n\
+                     0000000000000001\n\
+                     1111110000010000\n\
+                     0000000000001100\n\
+                     1110101010000111\n\
+                     0000000000000000\n\
+                     1111110000010000\n\
+                     0000000000000010\n\
+                     1110001100001000\n\
+                     0000000000001110\n\
+                     1110101010000111\n";
+
+        assert_eq!(
+            "    @SP\n\
+             \x20   D=M\n\
+             \x20   @LCL\n\
+             \x20   D=D-M\n\
+             \x20   @L_000A\n\
+             \x20   D;JGT\n\
+             \x20   @LCL\n\
+             \x20   D=M\n\
+             \x20   @L_000C\n\
+             \x20   0;JMP\n\
+             (L_000A)\n\
+             \x20   @SP\n\
+             \x20   D=M\n\
+             (L_000C)\n\
+             \x20   @ARG\n\
+             \x20   M=D\n\
+             (L_000E)\n\
+             \x20   @L_000E\n\
+             \x20   0;JMP\n",
+            disassemble_str(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn disassemble_constant_test() {
+        let input = "0000000000000010\n\
+                     1110110000010000\n\
+                     0100000000000000\n\
+                     1110110000010000\n\
+                     0000000000000111\n\
+                     1110101010000111\n";
+
+        assert_eq!(
+            "    @2\n\
+             \x20   D=A\n\
+             \x20   @SCREEN\n\
+             \x20   D=A\n\
+             \x20   @7\n\
+             \x20   0;JMP\n",
+            disassemble_str(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_test() {
+        let name = |value, usage: Usage| Some(format!("{}:{}", value, usage.jumps));
+        assert_eq!(Some("@2:true".to_string()), decode(2, Some(0xEA87), name));
+        assert_eq!(Some("@2".to_string()), decode(2, Some(0xEA87), |_, _| None));
+        assert_eq!(Some("AM=M+1".to_string()), decode(0xFDE8, None, name));
+        assert_eq!(None, decode(0x8000, None, name));
+
+        assert_eq!(
+            Usage {
+                jumps: false,
+                accesses_memory: true
+            },
+            Usage::of(Some(0xFC10))
+        );
+        assert_eq!(Usage::default(), Usage::of(Some(0x0010)));
+    }
+
+    #[test]
+    fn disassemble_error_test() {
+        let errors = disassemble_str("0000000000000010\n1000000000000000\n12\n").unwrap_err();
+        assert_eq!(1, errors.len());
+        assert_eq!(3, errors[0].line);
+
+        let errors = disassemble_str("1000000000000000\n").unwrap_err();
+        assert_eq!(1, errors.len());
+        assert!(matches!(errors[0].kind, ErrorKind::InvalidInstruction));
+
+        // The blank lines are counted in the line numbers.
+        let errors = disassemble_str("0000000000000010\n\n1000000000000000\n").unwrap_err();
+        assert_eq!(1, errors.len());
+        assert_eq!(3, errors[0].line);
+    }
+}
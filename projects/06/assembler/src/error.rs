@@ -0,0 +1,178 @@
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Io(io::Error),
+    InvalidDest,
+    InvalidComp,
+    /// Both A and M are used in comp. It holds the suggested mnemonic.
+    UnencodableComp(String),
+    InvalidJump,
+    InvalidSymbol,
+    MalformedLabel,
+    InvalidInstruction,
+    DuplicateLabel,
+    ConstantOutOfRange,
+    InvalidConstant,
+    NegativeConstant,
+    InvalidExpression,
+    UndefinedSymbol,
+    UnknownDirective,
+    MalformedDirective,
+    UnterminatedMacro,
+    MacroArgumentMismatch,
+    RecursiveMacro,
+    RecursiveInclude,
+    // Warnings.
+    UnusedLabel,
+    VariableOverlapsStack,
+    VariableOverlapsScreen,
+    RomOverflow,
+}
+
+impl ErrorKind {
+    /// Warnings do not prevent generating the codes.
+    pub fn is_warning(&self) -> bool {
+        use ErrorKind::*;
+        matches!(
+            self,
+            UnusedLabel | VariableOverlapsStack | VariableOverlapsScreen | RomOverflow
+        )
+    }
+
+    /// The hint to fix the error, if any.
+    pub fn help(&self) -> Option<String> {
+        match self {
+            ErrorKind::UnencodableComp(suggestion) => {
+                Some(format!("did you mean `{}`?", suggestion))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ErrorKind::*;
+        match self {
+            Io(e) => write!(f, "cannot read the source: {}", e),
+            InvalidDest => write!(f, "unknown dest mnemonic"),
+            InvalidComp => write!(f, "unknown comp mnemonic"),
+            UnencodableComp(_) => write!(f, "comp cannot use both A and M"),
+            InvalidJump => write!(f, "unknown jump mnemonic"),
+            InvalidSymbol => write!(f, "invalid symbol"),
+            MalformedLabel => write!(f, "malformed label"),
+            InvalidInstruction => write!(f, "invalid instruction"),
+            DuplicateLabel => write!(f, "symbol is already defined"),
+            ConstantOutOfRange => write!(f, "constant must be in 0..=32767"),
+            InvalidConstant => write!(f, "invalid constant"),
+            NegativeConstant => write!(
+                f,
+                "negative constant cannot be loaded by an A-instruction, \
+                 load the absolute value and negate it like `@1` and `A=-A`"
+            ),
+            InvalidExpression => write!(f, "invalid expression"),
+            UndefinedSymbol => write!(f, "expression contains undefined symbol"),
+            UnknownDirective => write!(f, "unknown directive"),
+            MalformedDirective => write!(f, "malformed directive"),
+            UnterminatedMacro => write!(f, "macro is not terminated by `.endm`"),
+            MacroArgumentMismatch => write!(f, "wrong number of macro arguments"),
+            RecursiveMacro => write!(f, "macro expansion is too deep"),
+            RecursiveInclude => write!(f, "file includes itself"),
+            UnusedLabel => write!(f, "label is never referenced"),
+            VariableOverlapsStack => write!(f, "variable is allocated in the stack from 256"),
+            VariableOverlapsScreen => write!(f, "variable is allocated in the screen from 0x4000"),
+            RomOverflow => write!(f, "program exceeds the ROM size of 32768 words"),
+        }
+    }
+}
+
+/// An error located at the offending text in the source file.
+#[derive(Debug)]
+pub struct AssembleError {
+    pub file: String,
+    /// 1-origin line number.
+    pub line: usize,
+    /// 1-origin column number.
+    pub column: usize,
+    /// The offending text.
+    pub text: String,
+    /// The whole source line which contains the offending text.
+    pub source: String,
+    pub kind: ErrorKind,
+}
+
+impl AssembleError {
+    /// Create an error pointing the given byte range of the source line.
+    pub fn new(kind: ErrorKind, line: usize, source: &str, range: Range<usize>) -> Self {
+        Self {
+            file: String::new(),
+            line,
+            column: source[..range.start].chars().count() + 1,
+            text: source[range].to_string(),
+            source: source.to_string(),
+            kind,
+        }
+    }
+
+    /// Set the file name unless it is already known.
+    pub fn in_file(mut self, file: &str) -> Self {
+        if self.file.is_empty() {
+            self.file = file.to_string();
+        }
+        self
+    }
+}
+
+impl fmt::Display for AssembleError {
+    /// Render the error like rustc does.
+    ///
+    /// ```text
+    /// error: unknown comp mnemonic `A+X`
+    ///  --> Foo.asm:3:3
+    ///   |
+    /// 3 | D=A+X
+    ///   |   ^^^
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = if self.kind.is_warning() {
+            "warning"
+        } else {
+            "error"
+        };
+
+        if self.line == 0 {
+            // The error is not related to any line.
+            return write!(f, "{}: {}\n --> {}", severity, self.kind, self.file);
+        }
+
+        if self.text.is_empty() {
+            writeln!(f, "{}: {}", severity, self.kind)?;
+        } else {
+            writeln!(f, "{}: {} `{}`", severity, self.kind, self.text)?;
+        }
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter, self.file, self.line, self.column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.column.saturating_sub(1)),
+            "^".repeat(self.text.chars().count().max(1))
+        )?;
+        if let Some(help) = self.kind.help() {
+            write!(f, "\n{} |\n{} = help: {}", gutter, gutter, help)?;
+        }
+
+        Ok(())
+    }
+}
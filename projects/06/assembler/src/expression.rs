@@ -0,0 +1,208 @@
+use crate::error::ErrorKind;
+use crate::parser;
+
+/// Check the given string looks like an expression rather than a symbol or a constant.
+pub fn is_expression(s: &str) -> bool {
+    let mut in_quote = false;
+    s.char_indices().any(|(i, c)| {
+        if c == '\'' {
+            in_quote = !in_quote;
+        }
+        !in_quote && ("+*()".contains(c) || c == '-' && i > 0)
+    })
+}
+
+/// Evaluate an expression such as `SCREEN+32*4` or `LOOP-1`.
+///
+/// The operands are constants in any form accepted by `parser::parse_constant` or symbols
+/// which are resolved by `resolve`. '+', '-', '*' and parentheses are supported.
+/// Whitespaces are ignored.
+pub fn evaluate<F: FnMut(&str) -> Option<u16>>(expr: &str, resolve: F) -> Result<u16, ErrorKind> {
+    let tokens = tokenize(expr);
+    let mut evaluator = Evaluator {
+        tokens,
+        index: 0,
+        resolve,
+    };
+
+    let value = evaluator.expression()?;
+    if evaluator.index != evaluator.tokens.len() {
+        return Err(ErrorKind::InvalidExpression);
+    }
+
+    if 0 <= value && value <= parser::MAX_CONSTANT as i64 {
+        Ok(value as u16)
+    } else {
+        Err(ErrorKind::ConstantOutOfRange)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Operand(String),
+    Operator(char),
+}
+
+fn tokenize(expr: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        if "+-*()".contains(c) {
+            tokens.push(Token::Operator(c));
+        } else if c == '\'' {
+            let mut operand = c.to_string();
+            for c in chars.by_ref() {
+                operand.push(c);
+                if c == '\'' {
+                    break;
+                }
+            }
+            tokens.push(Token::Operand(operand));
+        } else {
+            let mut operand = c.to_string();
+            while let Some(c) = chars.peek() {
+                if c.is_whitespace() || "+-*()'".contains(*c) {
+                    break;
+                }
+                operand.push(*c);
+                chars.next();
+            }
+            tokens.push(Token::Operand(operand));
+        }
+    }
+
+    tokens
+}
+
+struct Evaluator<F> {
+    tokens: Vec<Token>,
+    index: usize,
+    resolve: F,
+}
+
+impl<F: FnMut(&str) -> Option<u16>> Evaluator<F> {
+    fn peek_operator(&self) -> Option<char> {
+        match self.tokens.get(self.index) {
+            Some(Token::Operator(c)) => Some(*c),
+            _ => None,
+        }
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<i64, ErrorKind> {
+        let mut value = self.term()?;
+
+        while let Some(op @ '+') | Some(op @ '-') = self.peek_operator() {
+            self.index += 1;
+            let rhs = self.term()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+            check_range(value)?;
+        }
+
+        Ok(value)
+    }
+
+    // term := factor ('*' factor)*
+    fn term(&mut self) -> Result<i64, ErrorKind> {
+        let mut value = self.factor()?;
+
+        while let Some('*') = self.peek_operator() {
+            self.index += 1;
+            value *= self.factor()?;
+            check_range(value)?;
+        }
+
+        Ok(value)
+    }
+
+    // factor := '-' factor | '(' expression ')' | operand
+    fn factor(&mut self) -> Result<i64, ErrorKind> {
+        match self.tokens.get(self.index) {
+            Some(Token::Operator('-')) => {
+                self.index += 1;
+                Ok(-self.factor()?)
+            }
+            Some(Token::Operator('(')) => {
+                self.index += 1;
+                let value = self.expression()?;
+                if self.peek_operator() != Some(')') {
+                    return Err(ErrorKind::InvalidExpression);
+                }
+                self.index += 1;
+                Ok(value)
+            }
+            Some(Token::Operand(operand)) => {
+                self.index += 1;
+                if let Some(result) = parser::parse_constant(operand) {
+                    result.map(i64::from)
+                } else if parser::is_symbol(operand) {
+                    (self.resolve)(operand)
+                        .map(i64::from)
+                        .ok_or(ErrorKind::UndefinedSymbol)
+                } else {
+                    Err(ErrorKind::InvalidExpression)
+                }
+            }
+            _ => Err(ErrorKind::InvalidExpression),
+        }
+    }
+}
+
+/// Reject the intermediate values which are obviously too large.
+fn check_range(value: i64) -> Result<(), ErrorKind> {
+    if value.abs() <= u16::MAX as i64 * 2 {
+        Ok(())
+    } else {
+        Err(ErrorKind::ConstantOutOfRange)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(symbol: &str) -> Option<u16> {
+        match symbol {
+            "SCREEN" => Some(0x4000),
+            "LOOP" => Some(10),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn is_expression_test() {
+        assert!(is_expression("SCREEN+32"));
+        assert!(is_expression("LOOP-1"));
+        assert!(is_expression("(1)"));
+        assert!(is_expression("-LOOP+20"));
+        assert!(!is_expression("LOOP"));
+        assert!(!is_expression("'+'"));
+        assert!(!is_expression("-1"));
+    }
+
+    #[test]
+    fn evaluate_test() {
+        let eval = |expr| evaluate(expr, resolve).map_err(|e| e.to_string());
+
+        assert_eq!(Ok(0x4020), eval("SCREEN+32"));
+        assert_eq!(Ok(9), eval("LOOP-1"));
+        assert_eq!(Ok(0x4000 + 32 * 3 + 1), eval("SCREEN + 32*3 + 1"));
+        assert_eq!(Ok(0x4000 + 32 * 11), eval("SCREEN+32*(LOOP+1)"));
+        assert_eq!(Ok(0x4010), eval("SCREEN+0x10"));
+        assert_eq!(Ok(66), eval("'A'+1"));
+        assert_eq!(Ok(10), eval("-LOOP+20"));
+
+        let error = |kind: ErrorKind| Err(kind.to_string());
+        assert_eq!(error(ErrorKind::UndefinedSymbol), eval("FOO+1"));
+        assert_eq!(error(ErrorKind::ConstantOutOfRange), eval("SCREEN*2"));
+        assert_eq!(error(ErrorKind::ConstantOutOfRange), eval("LOOP-11"));
+        assert_eq!(error(ErrorKind::InvalidExpression), eval("LOOP+"));
+        assert_eq!(error(ErrorKind::InvalidExpression), eval("(LOOP+1"));
+        assert_eq!(error(ErrorKind::InvalidExpression), eval("LOOP 1"));
+    }
+}
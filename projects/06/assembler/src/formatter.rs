@@ -0,0 +1,189 @@
+use crate::ast::{Instruction, Value};
+use crate::parser::{CommandType, Parser};
+use std::io::Cursor;
+
+const INDENT: &str = "    ";
+
+/// A non-blank line of the formatted source.
+struct Line {
+    indented: bool,
+    code: String,
+    comment: Option<String>,
+}
+
+impl Line {
+    fn width(&self) -> usize {
+        let indent = if self.indented { INDENT.len() } else { 0 };
+        indent + self.code.chars().count()
+    }
+}
+
+/// Rewrite the source into the canonical layout.
+///
+/// Instructions are indented, while labels and directives are flush-left. The trailing comments
+/// are aligned in each block of lines separated by blank lines, and consecutive blank lines are
+/// merged. The other lines such as macro invocations are kept as they are except for the
+/// whitespaces.
+///
+/// The instructions are written as they are unless `canonical` is set, which rewrites them with
+/// the canonical mnemonics like `M=M+1` for `M=1+M` and `MD` for `DM`.
+pub fn format(src: &str, canonical: bool) -> String {
+    let mut blocks: Vec<Vec<Line>> = vec![Vec::new()];
+    for raw in src.lines() {
+        let (code, comment) = match raw.find("//") {
+            Some(i) => (raw[..i].trim(), Some(raw[i..].trim_end().to_string())),
+            None => (raw.trim(), None),
+        };
+
+        if code.is_empty() && comment.is_none() {
+            if !blocks.last().unwrap().is_empty() {
+                blocks.push(Vec::new());
+            }
+            continue;
+        }
+
+        let (indented, code) = if code.is_empty() {
+            (false, String::new())
+        } else {
+            format_code(code, canonical)
+        };
+        blocks.last_mut().unwrap().push(Line {
+            indented,
+            code,
+            comment,
+        });
+    }
+
+    let mut output = String::with_capacity(src.len());
+    for block in blocks.iter_mut().filter(|block| !block.is_empty()) {
+        if !output.is_empty() {
+            output.push('\n');
+        }
+
+        // The comment lines are indented as the following code.
+        let mut indented = false;
+        for line in block.iter_mut().rev() {
+            if line.code.is_empty() {
+                line.indented = indented;
+            } else {
+                indented = line.indented;
+            }
+        }
+
+        let column = block
+            .iter()
+            .filter(|line| !line.code.is_empty() && line.comment.is_some())
+            .map(Line::width)
+            .max()
+            .unwrap_or(0);
+        for line in block.iter() {
+            let mut text = String::new();
+            if line.indented {
+                text.push_str(INDENT);
+            }
+            text.push_str(&line.code);
+            if let Some(comment) = &line.comment {
+                if !line.code.is_empty() {
+                    text.push_str(&" ".repeat(column - line.width() + 1));
+                }
+                text.push_str(comment);
+            }
+            output.push_str(&text);
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+/// Format the code without comments. The flag tells whether the line is indented.
+fn format_code(code: &str, canonical: bool) -> (bool, String) {
+    let mut cursor = Cursor::new(code);
+    let parser = match Parser::new(&mut cursor) {
+        Ok(parser) => parser,
+        Err(_) => return (true, normalize_whitespace(code)),
+    };
+
+    if parser.command_type() == CommandType::Directive {
+        return (false, normalize_whitespace(code));
+    }
+
+    match parser.instruction() {
+        Ok(instruction @ Instruction::Label { .. }) => (false, instruction.to_string()),
+        // Keep the notation of the constant such as `0x4000` and `'A'`.
+        Ok(Instruction::Address {
+            value: Value::Constant(_),
+            span,
+        }) => (true, format!("@{}", &code[span])),
+        Ok(Instruction::Compute { span, .. }) if !canonical => {
+            (true, code[span].split_whitespace().collect())
+        }
+        Ok(instruction) => (true, instruction.to_string()),
+        Err(_) => (true, normalize_whitespace(code)),
+    }
+}
+
+fn normalize_whitespace(code: &str) -> String {
+    code.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_test() {
+        let src = "\n\
+                   // Count up.\n\
+                   .equ   N  10\n\
+                   \n\
+                   \n\
+                   @ i\n\
+                   M = 0   // i = 0\n\
+                   \x20 (LOOP)\n\
+                   // Increment.\n\
+                   \x20      M=1+M // i++\n\
+                   @ 0x4000\n\
+                   DM=A\n\
+                   INC  i\n\
+                   \x20   @ LOOP // loop\n\
+                   0 ; JMP\n\
+                   \n";
+
+        assert_eq!(
+            "// Count up.\n\
+             .equ N 10\n\
+             \n\
+             \x20   @i\n\
+             \x20   M=0   // i = 0\n\
+             (LOOP)\n\
+             \x20   // Increment.\n\
+             \x20   M=1+M // i++\n\
+             \x20   @0x4000\n\
+             \x20   DM=A\n\
+             \x20   INC i\n\
+             \x20   @LOOP // loop\n\
+             \x20   0;JMP\n",
+            format(src, false)
+        );
+
+        let canonical = format(src, true);
+        assert!(canonical.contains("    M=M+1 // i++\n    @0x4000\n    MD=A\n"));
+        assert_eq!(canonical, format(&canonical, true));
+    }
+
+    #[test]
+    fn format_idempotent_test() {
+        let src = "(LOOP) // start\n@' ' // space\nD=A\n@LOOP\nD;JGT // loop";
+        let formatted = format(src, false);
+        assert_eq!(
+            "(LOOP)    // start\n\
+             \x20   @' '  // space\n\
+             \x20   D=A\n\
+             \x20   @LOOP\n\
+             \x20   D;JGT // loop\n",
+            formatted
+        );
+        assert_eq!(formatted, format(&formatted, false));
+    }
+}
@@ -0,0 +1,814 @@
+pub mod ast;
+pub mod code;
+pub mod disassembler;
+pub mod error;
+pub mod expression;
+pub mod formatter;
+pub mod linker;
+pub mod listing;
+pub mod macros;
+pub mod optimizer;
+pub mod output;
+pub mod parser;
+pub mod stream;
+pub mod symbol_table;
+
+use ast::{Instruction, Value};
+use error::{AssembleError, ErrorKind};
+use linker::Linked;
+use parser::{CommandType, Parser};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::prelude::*;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use symbol_table::{SymbolKind, SymbolTable};
+
+static VARIABLE_ADDRESS_BEGIN: u16 = 16;
+static STACK_ADDRESS_BEGIN: u16 = 256;
+static SCREEN_ADDRESS_BEGIN: u16 = 0x4000;
+static ROM_SIZE: usize = 0x8000;
+
+/// The result of assembling a program.
+#[derive(Debug)]
+pub struct Program {
+    /// The parsed instructions including labels.
+    pub instructions: Vec<Instruction>,
+    /// 1-origin line numbers of the instructions in the source.
+    pub lines: Vec<usize>,
+    /// The symbol table after all labels and variables are resolved.
+    pub symbol_table: SymbolTable,
+    /// The encoded words in ROM order.
+    pub words: Vec<u16>,
+    /// The names and the expressions of the constants defined by `.equ` in the source order.
+    pub constants: Vec<(String, String)>,
+    /// The problems which do not prevent generating the codes.
+    pub warnings: Vec<AssembleError>,
+}
+
+/// Assemble the given source into the machine codes.
+pub fn assemble(src: &str) -> Result<Vec<u16>, Vec<AssembleError>> {
+    assemble_program(src).map(|program| program.words)
+}
+
+/// Assemble the given source and keep the intermediate results.
+///
+/// The macros are expanded in advance, and the line numbers in the result point the original
+/// source. The lines generated by a macro invocation point the invocation.
+pub fn assemble_program(src: &str) -> Result<Program, Vec<AssembleError>> {
+    let expansion = macros::expand(src)?;
+    let mut symbol_table = SymbolTable::new();
+
+    let to_origin = |mut error: AssembleError| {
+        error.line = expansion.origin(error.line);
+        error
+    };
+
+    // The instructions are checked even if the labels or the constants have errors.
+    let labels = pass1(&mut Cursor::new(&expansion.text), &mut symbol_table);
+    let result = pass2(&mut Cursor::new(&expansion.text), symbol_table);
+    let result = match labels {
+        Ok(()) => result,
+        Err(mut errors) => {
+            errors.extend(result.err().unwrap_or_default());
+            errors.sort_by_key(|error| error.line);
+            Err(errors)
+        }
+    };
+
+    result
+        .map(|mut program| {
+            for line in program.lines.iter_mut() {
+                *line = expansion.origin(*line);
+            }
+            program.warnings = program.warnings.into_iter().map(to_origin).collect();
+            program
+        })
+        .map_err(|errors| errors.into_iter().map(to_origin).collect())
+}
+
+/// Assemble the given files and the files included by them into one program.
+///
+/// The files are read by `load`. The errors and the warnings point the original files, while
+/// the line numbers in the program point the combined text.
+pub fn assemble_files<F>(
+    paths: &[PathBuf],
+    load: F,
+) -> Result<(Linked, Program), Vec<AssembleError>>
+where
+    F: FnMut(&Path) -> io::Result<String>,
+{
+    let linked = linker::link(paths, load)?;
+
+    match assemble_program(&linked.text) {
+        Ok(mut program) => {
+            program.warnings = program
+                .warnings
+                .into_iter()
+                .map(|warning| linked.locate(warning))
+                .collect();
+            Ok((linked, program))
+        }
+        Err(errors) => Err(errors
+            .into_iter()
+            .map(|error| linked.locate(error))
+            .collect()),
+    }
+}
+
+/// Write the words in the text format of .hack files.
+pub fn write_hack<W: Write>(words: &[u16], dst: &mut W) -> io::Result<()> {
+    for word in words {
+        writeln!(dst, "{:016b}", word)?;
+    }
+
+    Ok(())
+}
+
+/// Read the all lines in order to create symbol table.
+pub fn pass1<R: BufRead>(
+    src: &mut R,
+    symbol_table: &mut SymbolTable,
+) -> Result<(), Vec<AssembleError>> {
+    let mut current_address = 0;
+    let mut parser = Parser::new(src).map_err(|e| vec![e])?;
+    let mut errors = Vec::new();
+    let mut constants = Vec::new();
+
+    while parser.has_more_commands() {
+        if parser.command_type() == CommandType::Directive {
+            match parser.directive().as_str() {
+                "equ" => match read_constant(&parser) {
+                    Ok(constant) => constants.push(constant),
+                    Err(e) => errors.push(e),
+                },
+                _ => {
+                    let range = parser.directive_range();
+                    errors.push(parser.error(range, ErrorKind::UnknownDirective));
+                }
+            }
+        } else if parser.command_type() == CommandType::Label {
+            match parser.instruction() {
+                Ok(Instruction::Label { name, span }) => {
+                    let result = define_label(symbol_table, name, current_address);
+                    errors.extend(result.err().map(|kind| parser.error_at(&span, kind)));
+                }
+                Ok(_) => unreachable!(),
+                Err(e) => errors.extend(e),
+            }
+        } else {
+            current_address += 1;
+        }
+
+        parser.advance().map_err(|e| vec![e])?;
+    }
+
+    define_constants(constants, symbol_table, |_| {}, &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Generate codes.
+pub fn pass2<R: BufRead>(
+    src: &mut R,
+    mut symbol_table: SymbolTable,
+) -> Result<Program, Vec<AssembleError>> {
+    let mut parser = Parser::new(src).map_err(|e| vec![e])?;
+    let mut var_address = VARIABLE_ADDRESS_BEGIN;
+    let mut instructions = Vec::new();
+    let mut lines = Vec::new();
+    let mut words = Vec::new();
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut rom_address = 0;
+    let mut referenced_symbols = HashSet::new();
+    let mut unused_labels = HashMap::new();
+    let mut constants = Vec::new();
+
+    while parser.has_more_commands() {
+        if parser.command_type() == CommandType::Directive {
+            if parser.directive() == "equ" {
+                // Mark the symbols used to define the constant.
+                let args = parser.arguments();
+                let expr = args.get(1..).unwrap_or_default().join(" ");
+                let _ = expression::evaluate(&expr, |s| {
+                    referenced_symbols.insert(s.to_string());
+                    symbol_table.get_address(s)
+                });
+                if let Some(name) = args.first() {
+                    constants.push((name.clone(), expr));
+                }
+            }
+
+            parser.advance().map_err(|e| vec![e])?;
+            continue;
+        }
+
+        let instruction = match parser.instruction() {
+            Ok(instruction) => instruction,
+            Err(e) => {
+                // The errors of the labels are reported by pass1.
+                if parser.command_type() != CommandType::Label {
+                    errors.extend(e);
+                }
+                parser.advance().map_err(|e| vec![e])?;
+                continue;
+            }
+        };
+        lines.push(parser.line_number());
+
+        if parser.command_type() != CommandType::Label {
+            if rom_address == ROM_SIZE {
+                warnings.push(parser.error(parser.command_range(), ErrorKind::RomOverflow));
+            }
+            rom_address += 1;
+        }
+
+        match &instruction {
+            Instruction::Address { value, span } => {
+                let n = match value {
+                    Value::Constant(n) => Some(*n),
+                    Value::Expression(expr) => {
+                        let result = expression::evaluate(expr, |s| {
+                            referenced_symbols.insert(s.to_string());
+                            symbol_table.get_address(s)
+                        });
+                        match result {
+                            Ok(n) => Some(n),
+                            Err(kind) => {
+                                errors.push(parser.error_at(span, kind));
+                                None
+                            }
+                        }
+                    }
+                    Value::Symbol(symbol) => {
+                        if let Some(n) = symbol_table.get_address(symbol) {
+                            // Use existing variable or label.
+                            referenced_symbols.insert(symbol.clone());
+                            Some(n)
+                        } else {
+                            // Allocate new variable.
+                            let (n, warning) = allocate_variable(
+                                &mut symbol_table,
+                                &mut var_address,
+                                symbol.clone(),
+                            );
+                            if let Some(kind) = warning {
+                                warnings.push(parser.error_at(span, kind));
+                            }
+                            Some(n)
+                        }
+                    }
+                };
+
+                if let Some(n) = n {
+                    words.push(n);
+                }
+            }
+            Instruction::Compute {
+                dest, comp, jump, ..
+            } => words.push(code::encode(*dest, *comp, *jump)),
+            Instruction::Label { name, span } => {
+                let warning = parser.error_at(span, ErrorKind::UnusedLabel);
+                unused_labels.entry(name.clone()).or_insert(warning);
+            }
+        }
+        instructions.push(instruction);
+
+        parser.advance().map_err(|e| vec![e])?;
+    }
+
+    warnings.extend(unused_label_warnings(unused_labels, &referenced_symbols));
+
+    if errors.is_empty() {
+        Ok(Program {
+            instructions,
+            lines,
+            symbol_table,
+            words,
+            warnings,
+            constants,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+/// `.equ NAME EXPR` waiting for the labels to be defined.
+struct Constant {
+    name: String,
+    expr: String,
+    /// The location of the name to report the duplicate definition.
+    location: AssembleError,
+    /// The location of the expression to report the evaluation error.
+    value_location: AssembleError,
+}
+
+fn read_constant<T: BufRead>(parser: &Parser<T>) -> Result<Constant, AssembleError> {
+    let args = parser.argument_ranges();
+    if args.len() < 2 {
+        let range = parser.command_range();
+        return Err(parser.error(range, ErrorKind::MalformedDirective));
+    }
+    if !parser::is_symbol(&parser.arguments()[0]) {
+        return Err(parser.error(args[0].clone(), ErrorKind::InvalidSymbol));
+    }
+
+    let range = args[1].start..args[args.len() - 1].end;
+    Ok(Constant {
+        name: parser.arguments().remove(0),
+        expr: parser.arguments()[1..].join(" "),
+        location: parser.error(args[0].clone(), ErrorKind::DuplicateLabel),
+        value_location: parser.error(range, ErrorKind::InvalidExpression),
+    })
+}
+
+/// Evaluate the constants after all labels are defined.
+/// `reference` is called with the symbols used in the expressions.
+///
+/// A constant which cannot be evaluated is defined as 0 not to report its uses as undefined.
+fn define_constants<F: FnMut(&str)>(
+    constants: Vec<Constant>,
+    symbol_table: &mut SymbolTable,
+    mut reference: F,
+    errors: &mut Vec<AssembleError>,
+) {
+    for mut constant in constants {
+        if symbol_table.get_address(&constant.name).is_some() {
+            errors.push(constant.location);
+            continue;
+        }
+
+        let result = expression::evaluate(&constant.expr, |s| {
+            reference(s);
+            symbol_table.get_address(s)
+        });
+        match result {
+            Ok(value) => symbol_table.add_entry(constant.name, value, SymbolKind::Constant),
+            Err(kind) => {
+                symbol_table.add_entry(constant.name, 0, SymbolKind::Constant);
+                constant.value_location.kind = kind;
+                errors.push(constant.value_location);
+            }
+        }
+    }
+}
+
+fn define_label(
+    symbol_table: &mut SymbolTable,
+    name: String,
+    address: u16,
+) -> Result<(), ErrorKind> {
+    if symbol_table.get_address(&name).is_some() {
+        Err(ErrorKind::DuplicateLabel)
+    } else {
+        symbol_table.add_entry(name, address, SymbolKind::Label);
+        Ok(())
+    }
+}
+
+/// Allocate a new variable and warn if it collides with the stack or the screen.
+fn allocate_variable(
+    symbol_table: &mut SymbolTable,
+    var_address: &mut u16,
+    symbol: String,
+) -> (u16, Option<ErrorKind>) {
+    let warning = if *var_address == STACK_ADDRESS_BEGIN {
+        Some(ErrorKind::VariableOverlapsStack)
+    } else if *var_address == SCREEN_ADDRESS_BEGIN {
+        Some(ErrorKind::VariableOverlapsScreen)
+    } else {
+        None
+    };
+
+    let n = *var_address;
+    symbol_table.add_entry(symbol, n, SymbolKind::Variable);
+    *var_address += 1;
+    (n, warning)
+}
+
+/// The warnings for the labels which are not referenced, in the order of the lines.
+fn unused_label_warnings(
+    labels: HashMap<String, AssembleError>,
+    referenced_symbols: &HashSet<String>,
+) -> Vec<AssembleError> {
+    let mut warnings: Vec<_> = labels
+        .into_iter()
+        .filter(|(label, _)| !referenced_symbols.contains(label))
+        .map(|(_, warning)| warning)
+        .collect();
+    warnings.sort_by_key(|warning| warning.line);
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use code::{Comp, Dest, Jump};
+
+    fn assemble_to_string(src: &str) -> Result<String, Vec<AssembleError>> {
+        let words = assemble(src)?;
+        let mut output = Vec::<u8>::new();
+        write_hack(&words, &mut output).unwrap();
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn assemble_test() {
+        let input = "@2\n\
+                             D=A\n\
+                             @3\n\
+                             D=D+A\n\
+                             @0\n\
+                             M=D";
+        assert_eq!(
+            "0000000000000010\n\
+             1110110000010000\n\
+             0000000000000011\n\
+             1110000010010000\n\
+             0000000000000000\n\
+             1110001100001000\n",
+            assemble_to_string(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn assemble_with_label_test() {
+        let input = "@R0\n\
+                             D=M              // D = first number\n\
+                             @R1\n\
+                             D=D-M            // D = first number - second number\n\
+                             @OUTPUT_FIRST\n\
+                             D;JGT            // if D>0 (first is greater) goto output_first\n\
+                             @R1\n\
+                             D=M              // D = second number\n\
+                             @OUTPUT_D\n\
+                             0;JMP            // goto output_d\n\
+                          (OUTPUT_FIRST)\n\
+                             @R0             \n\
+                             D=M              // D = first number\n\
+                          (OUTPUT_D)\n\
+                             @R2\n\
+                             M=D              // M[2] = D (greatest number)\n\
+                          (INFINITE_LOOP)\n\
+                             @INFINITE_LOOP\n\
+                             0;JMP            // infinite loop";
+
+        assert_eq!(
+            "0000000000000000\n\
+             1111110000010000\n\
+             0000000000000001\n\
+             1111010011010000\n\
+             0000000000001010\n\
+             1110001100000001\n\
+             0000000000000001\n\
+             1111110000010000\n\
+             0000000000001100\n\
+             1110101010000111\n\
+             0000000000000000\n\
+             1111110000010000\n\
+             0000000000000010\n\
+             1110001100001000\n\
+             0000000000001110\n\
+             1110101010000111\n",
+            assemble_to_string(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn assemble_error_test() {
+        let input = "@2\n\
+                             D=A+M\n\
+                             (LOOP\n\
+                             @1x\n\
+                             X=D;JMQ";
+        let errors = assemble(input).unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.kind.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                (2, ErrorKind::UnencodableComp("D+M".into()).to_string()),
+                (3, ErrorKind::MalformedLabel.to_string()),
+                (4, ErrorKind::InvalidConstant.to_string()),
+                (5, ErrorKind::InvalidDest.to_string()),
+                (5, ErrorKind::InvalidJump.to_string()),
+            ],
+            kinds
+        );
+
+        let input = "@2\n\
+                             D=A+M\n\
+                             @1x\n\
+                             X=D;JMQ";
+        let errors = assemble(input).unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.text.as_str(), e.kind.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    2,
+                    "A+M",
+                    ErrorKind::UnencodableComp("D+M".into()).to_string()
+                ),
+                (3, "1x", ErrorKind::InvalidConstant.to_string()),
+                (4, "X", ErrorKind::InvalidDest.to_string()),
+                (4, "JMQ", ErrorKind::InvalidJump.to_string()),
+            ],
+            kinds
+        );
+        let error = errors.into_iter().next().unwrap().in_file("test.asm");
+        assert_eq!(
+            "error: comp cannot use both A and M `A+M`\n \
+             --> test.asm:2:3\n  \
+             |\n\
+             2 | D=A+M\n  \
+             |   ^^^\n  \
+             |\n  \
+             = help: did you mean `D+M`?",
+            error.to_string()
+        );
+
+        let program = assemble_program("AM=M+D\nD=1+D\nDM=A&D\nD=0-M").unwrap();
+        let canonical = assemble_program("AM=D+M\nD=D+1\nMD=D&A\nD=-M").unwrap();
+        assert_eq!(canonical.words, program.words);
+    }
+
+    #[test]
+    fn disassemble_round_trip_test() {
+        let input = "@R0\n\
+                     D=M\n\
+                     @SCREEN\n\
+                     D=D+A\n\
+                  (LOOP)\n\
+                     @LOOP\n\
+                     D;JGT\n\
+                     @i\n\
+                     AM=M-1\n\
+                     @END\n\
+                     0;JMP\n\
+                  (END)";
+        let output = assemble_to_string(input).unwrap();
+
+        let mut disassembled = Vec::<u8>::new();
+        assert!(disassembler::disassemble(&mut Cursor::new(&output), &mut disassembled).is_ok());
+
+        let reassembled = assemble_to_string(&String::from_utf8(disassembled).unwrap()).unwrap();
+        assert_eq!(output, reassembled);
+    }
+
+    #[test]
+    fn assemble_program_test() {
+        let input = "@i\n\
+                  (LOOP)\n\
+                     M=M+1\n\
+                     @LOOP\n\
+                     0;JMP";
+        let program = assemble_program(input).unwrap();
+
+        assert_eq!(
+            vec![
+                Instruction::Address {
+                    value: Value::Symbol("i".to_string()),
+                    span: 1..2,
+                },
+                Instruction::Label {
+                    name: "LOOP".to_string(),
+                    span: 1..5,
+                },
+                Instruction::Compute {
+                    dest: Some(Dest::M),
+                    comp: Comp::MPlusOne,
+                    jump: None,
+                    span: 0..5,
+                },
+                Instruction::Address {
+                    value: Value::Symbol("LOOP".to_string()),
+                    span: 1..5,
+                },
+                Instruction::Compute {
+                    dest: None,
+                    comp: Comp::Zero,
+                    jump: Some(Jump::Jmp),
+                    span: 0..5,
+                },
+            ],
+            program.instructions
+        );
+        assert_eq!(Some(16), program.symbol_table.get_address("i"));
+        assert_eq!(Some(1), program.symbol_table.get_address("LOOP"));
+        assert_eq!(vec![1, 2, 3, 4, 5], program.lines);
+        assert_eq!(vec![16, 0xFDC8, 1, 0xEA87], program.words);
+    }
+
+    #[test]
+    fn assemble_label_error_test() {
+        let errors = assemble("(LOOP)\n@LOOP\n(LOOP)\n(SCREEN)\n0;JMP").unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.text.as_str(), e.kind.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                (3, "LOOP", ErrorKind::DuplicateLabel.to_string()),
+                (4, "SCREEN", ErrorKind::DuplicateLabel.to_string()),
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn assemble_constant_range_test() {
+        assert_eq!(vec![32767], assemble("@32767").unwrap());
+
+        for src in ["@32768", "@65535", "@99999999999"].iter() {
+            let errors = assemble(src).unwrap_err();
+            assert_eq!(1, errors.len());
+            assert!(matches!(errors[0].kind, ErrorKind::ConstantOutOfRange));
+        }
+    }
+
+    #[test]
+    fn assemble_literal_test() {
+        assert_eq!(
+            vec![0x4000, 0x6000, 10, 65, 32, 32767],
+            assemble("@0x4000\n@0x6000\n@0b1010\n@'A'\n@' '\n@0x7FFF").unwrap()
+        );
+
+        let errors = assemble("@-1\n@0xG").unwrap_err();
+        assert_eq!(2, errors.len());
+        assert!(matches!(errors[0].kind, ErrorKind::NegativeConstant));
+        assert_eq!("-1", errors[0].text);
+        assert!(matches!(errors[1].kind, ErrorKind::InvalidConstant));
+    }
+
+    #[test]
+    fn assemble_expression_test() {
+        let input = ".equ ROW 32\n\
+                     .equ LAST_ROW ROW * 255\n\
+                     .equ END_ADDRESS SCREEN + LAST_ROW + 31\n\
+                     (LOOP)\n\
+                     @SCREEN+32\n\
+                     @LOOP-1+2\n\
+                     @END_ADDRESS\n\
+                     @ 'A' + ROW\n\
+                     @END\n\
+                     0;JMP\n\
+                     (END)";
+        let program = assemble_program(input).unwrap();
+        assert_eq!(
+            vec![0x4020, 1, 0x4000 + 32 * 255 + 31, 65 + 32, 6, 0xEA87],
+            program.words
+        );
+        assert_eq!(vec![4, 5, 6, 7, 8, 9, 10, 11], program.lines);
+        assert_eq!(
+            Some(SymbolKind::Constant),
+            program.symbol_table.get_kind("ROW")
+        );
+        assert!(program.warnings.is_empty());
+    }
+
+    #[test]
+    fn assemble_expression_error_test() {
+        let input = ".equ\n\
+                     .equ 1X 2\n\
+                     .equ A1 UNKNOWN+1\n\
+                     .equ LOOP 1\n\
+                     .org 0\n\
+                     (LOOP)\n\
+                     @LOOP+";
+        let errors = assemble(input).unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.text.as_str(), e.kind.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                (1, ".equ", ErrorKind::MalformedDirective.to_string()),
+                (2, "1X", ErrorKind::InvalidSymbol.to_string()),
+                (3, "UNKNOWN+1", ErrorKind::UndefinedSymbol.to_string()),
+                (4, "LOOP", ErrorKind::DuplicateLabel.to_string()),
+                (5, "org", ErrorKind::UnknownDirective.to_string()),
+                (7, "LOOP+", ErrorKind::InvalidExpression.to_string()),
+            ],
+            kinds
+        );
+
+        let errors = assemble("(LOOP)\n@LOOP+").unwrap_err();
+        assert_eq!(1, errors.len());
+        assert!(matches!(errors[0].kind, ErrorKind::InvalidExpression));
+
+        // The uses of a constant which has an error are not reported again.
+        let errors = assemble(".equ ROW 32+\n@ROW+1\n(LOOP\n@LOOP\nD=A+M").unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(vec![1, 3, 5], lines);
+    }
+
+    #[test]
+    fn assemble_macro_test() {
+        let input = ".macro SET addr, value\n\
+                         @value\n\
+                         D=A\n\
+                         @addr\n\
+                         M=D\n\
+                     .endm\n\
+                     SET R0, 5\n\
+                     SET i, SCREEN+1\n\
+                     SET R1, 'A'\n\
+                     SET i, 1\n\
+                     D=A+M";
+        let errors = assemble(input).unwrap_err();
+        assert_eq!(1, errors.len());
+        assert_eq!(11, errors[0].line);
+
+        let input = &input[..input.rfind('\n').unwrap()];
+        let program = assemble_program(input).unwrap();
+        assert_eq!(
+            vec![5, 0xEC10, 0, 0xE308, 0x4001, 0xEC10, 16, 0xE308, 65, 0xEC10, 1, 0xE308],
+            program.words[..12].to_vec()
+        );
+        assert_eq!(
+            vec![7, 7, 7, 7, 8, 8, 8, 8, 9, 9, 9, 9],
+            program.lines[..12].to_vec()
+        );
+
+        let errors =
+            assemble(".macro SET addr, value\n@value\n@addr\n.endm\nSET R0, 5+").unwrap_err();
+        assert_eq!(1, errors.len());
+        assert_eq!(5, errors[0].line);
+        assert_eq!("5+", errors[0].text);
+    }
+
+    #[test]
+    fn assemble_files_test() {
+        let load = |path: &Path| match path.to_str().unwrap() {
+            "main.asm" => Ok("(.loop)\n@.loop\n0;JMP\n.include \"mult.asm\"".to_string()),
+            "mult.asm" => Ok("(MULT)\n(.loop)\n@.loop\nD;JGT\n@MULT\nD=A+M".to_string()),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+        };
+
+        let errors = assemble_files(&[PathBuf::from("main.asm")], load).unwrap_err();
+        assert_eq!(1, errors.len());
+        assert_eq!("mult.asm", errors[0].file);
+        assert_eq!(6, errors[0].line);
+
+        let load = |path: &Path| match path.to_str().unwrap() {
+            "main.asm" => Ok("(.loop)\n@.loop\n0;JMP\n.include \"mult.asm\"".to_string()),
+            "mult.asm" => Ok("(MULT)\n(.loop)\n@.loop\nD;JGT".to_string()),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
+        };
+        let (linked, program) = assemble_files(&[PathBuf::from("main.asm")], load).unwrap();
+        assert_eq!(vec![0, 0xEA87, 2, 0xE301], program.words);
+        assert_eq!(Some(0), program.symbol_table.get_address("main$.loop"));
+        assert_eq!(Some(2), program.symbol_table.get_address("mult$.loop"));
+
+        let warning = &program.warnings[0];
+        assert!(matches!(warning.kind, ErrorKind::UnusedLabel));
+        assert_eq!(("mult.asm", 1), (warning.file.as_str(), warning.line));
+        assert_eq!(vec!["main.asm", "mult.asm"], linked.files);
+    }
+
+    #[test]
+    fn assemble_warning_test() {
+        let mut src = String::from("(UNUSED)\n(USED)\n@USED\n0;JMP\n");
+        for i in 0..(0x4000 - 16 + 1) {
+            src.push_str(&format!("@v{}\n", i));
+        }
+        let program = assemble_program(&src).unwrap();
+        let warnings: Vec<_> = program
+            .warnings
+            .iter()
+            .map(|e| (e.line, e.text.as_str(), e.kind.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    5 + 240,
+                    "v240",
+                    ErrorKind::VariableOverlapsStack.to_string()
+                ),
+                (
+                    5 + 0x3FF0,
+                    "v16368",
+                    ErrorKind::VariableOverlapsScreen.to_string()
+                ),
+                (1, "UNUSED", ErrorKind::UnusedLabel.to_string()),
+            ],
+            warnings
+        );
+        assert!(program.warnings.iter().all(|e| e.kind.is_warning()));
+
+        let src = "@0\n".repeat(0x8001);
+        let program = assemble_program(&src).unwrap();
+        assert_eq!(1, program.warnings.len());
+        assert_eq!(0x8001, program.warnings[0].line);
+        assert!(matches!(program.warnings[0].kind, ErrorKind::RomOverflow));
+    }
+}
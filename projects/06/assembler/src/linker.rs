@@ -0,0 +1,371 @@
+use crate::error::{AssembleError, ErrorKind};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The limit of nested includes to detect the cycles written in different paths.
+const MAX_DEPTH: usize = 32;
+
+/// The sources combined into one.
+#[derive(Debug)]
+pub struct Linked {
+    pub text: String,
+    /// The file names in the order they are loaded.
+    pub files: Vec<String>,
+    /// The index of the file and the 1-origin line number in the file for each line of `text`.
+    pub origins: Vec<(usize, usize)>,
+    /// The scope of the local symbols of each file.
+    pub scopes: Vec<String>,
+}
+
+impl Linked {
+    /// Point the original file and line of the error in the combined text.
+    ///
+    /// The local symbols in the error are restored to the names written in the file.
+    pub fn locate(&self, mut error: AssembleError) -> AssembleError {
+        if let Some((file, line)) = error.line.checked_sub(1).and_then(|i| self.origins.get(i)) {
+            error.file = self.files[*file].clone();
+            error.line = *line;
+            delocalize(&mut error, &self.scopes[*file]);
+        }
+
+        error
+    }
+}
+
+/// Combine the given files into one source.
+///
+/// `.include "file.asm"` is replaced with the contents of the file, which is searched relative to
+/// the including file. The labels and other symbols beginning with '.' are local to the file
+/// where they are written, so they are renamed to `SCOPE$.name` with the unique scope per file.
+pub fn link<F>(paths: &[PathBuf], mut load: F) -> Result<Linked, Vec<AssembleError>>
+where
+    F: FnMut(&Path) -> io::Result<String>,
+{
+    let mut linker = Linker {
+        load: &mut load,
+        linked: Linked {
+            text: String::new(),
+            files: Vec::new(),
+            origins: Vec::new(),
+            scopes: Vec::new(),
+        },
+        scopes: HashSet::new(),
+        including: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    for path in paths {
+        match (linker.load)(path) {
+            Ok(src) => linker.append(path, &src),
+            Err(e) => linker.errors.push(AssembleError {
+                file: path.to_string_lossy().to_string(),
+                line: 0,
+                column: 0,
+                text: String::new(),
+                source: String::new(),
+                kind: ErrorKind::Io(e),
+            }),
+        }
+    }
+
+    if linker.errors.is_empty() {
+        Ok(linker.linked)
+    } else {
+        Err(linker.errors)
+    }
+}
+
+struct Linker<'a, F> {
+    load: &'a mut F,
+    linked: Linked,
+    scopes: HashSet<String>,
+    /// The files being included to detect the cycles.
+    including: Vec<PathBuf>,
+    errors: Vec<AssembleError>,
+}
+
+impl<'a, F> Linker<'a, F>
+where
+    F: FnMut(&Path) -> io::Result<String>,
+{
+    fn append(&mut self, path: &Path, src: &str) {
+        let file_index = self.linked.files.len();
+        let file_name = path.to_string_lossy().to_string();
+        self.linked.files.push(file_name.clone());
+        self.including.push(path.to_path_buf());
+
+        let scope = self.new_scope(path);
+        self.linked.scopes.push(scope.clone());
+        for (i, line) in src.lines().enumerate() {
+            let line_number = i + 1;
+            let error = |kind, range| {
+                AssembleError::new(kind, line_number, line, range).in_file(&file_name)
+            };
+
+            let code = line.split("//").next().unwrap_or("");
+            let trimmed = code.trim_start();
+            if !trimmed.starts_with(".include") {
+                self.push(&localize(line, &scope), file_index, line_number);
+                continue;
+            }
+
+            let begin = code.len() - trimmed.len();
+            let range = begin..begin + ".include".len();
+            let argument = trimmed[".include".len()..].trim();
+            if argument.len() < 2 || !argument.starts_with('"') || !argument.ends_with('"') {
+                self.errors
+                    .push(error(ErrorKind::MalformedDirective, range));
+                continue;
+            }
+
+            let include_path = path
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(&argument[1..argument.len() - 1]);
+            let argument_begin = code.find(argument).unwrap();
+            let argument_range = argument_begin..argument_begin + argument.len();
+
+            if self.including.contains(&include_path) || self.including.len() >= MAX_DEPTH {
+                self.errors
+                    .push(error(ErrorKind::RecursiveInclude, argument_range));
+                continue;
+            }
+
+            // Keep the directive line as an empty line.
+            self.push("", file_index, line_number);
+            match (self.load)(&include_path) {
+                Ok(included) => self.append(&include_path, &included),
+                Err(e) => self.errors.push(error(ErrorKind::Io(e), argument_range)),
+            }
+        }
+
+        self.including.pop();
+    }
+
+    fn push(&mut self, line: &str, file_index: usize, line_number: usize) {
+        self.linked.text.push_str(line);
+        self.linked.text.push('\n');
+        self.linked.origins.push((file_index, line_number));
+    }
+
+    /// Create the unique scope name from the file name.
+    fn new_scope(&mut self, path: &Path) -> String {
+        let stem: String = path
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+        let stem = if stem.is_empty() {
+            "file".to_string()
+        } else {
+            stem
+        };
+
+        let mut scope = stem.clone();
+        let mut n = 1;
+        while self.scopes.contains(&scope) {
+            scope = format!("{}_{}", stem, n);
+            n += 1;
+        }
+        self.scopes.insert(scope.clone());
+
+        scope
+    }
+}
+
+/// Prefix the symbols beginning with '.' with the scope.
+/// The directives such as `.equ` at the beginning of the line are kept.
+fn localize(line: &str, scope: &str) -> String {
+    let code_len = line.find("//").unwrap_or(line.len());
+    let mut result = String::with_capacity(line.len());
+    let mut in_quote = false;
+    let mut is_first = true;
+    let mut prev: Option<char> = None;
+
+    for c in line[..code_len].chars() {
+        if c == '\'' {
+            in_quote = !in_quote;
+        }
+
+        let begins_symbol = prev.map_or(true, |p| !is_symbol_char(p));
+        if c == '.' && !in_quote && begins_symbol && !is_first {
+            result.push_str(scope);
+            result.push('$');
+        }
+        if !c.is_whitespace() {
+            is_first = false;
+        }
+
+        result.push(c);
+        prev = Some(c);
+    }
+    result.push_str(&line[code_len..]);
+
+    result
+}
+
+/// Remove the scope prefixed by `localize` from the source and the text of the error.
+fn delocalize(error: &mut AssembleError, scope: &str) {
+    let prefix = format!("{}$", scope);
+    let source = &error.source;
+    let start = source
+        .char_indices()
+        .nth(error.column.saturating_sub(1))
+        .map_or(source.len(), |(i, _)| i);
+    let end = start + error.text.len();
+
+    let mut restored = String::with_capacity(source.len());
+    let (mut new_start, mut new_end) = (start, end);
+    let mut copied = 0;
+    for (i, _) in source.match_indices(&format!("{}.", prefix)) {
+        let begins_symbol = source[..i]
+            .chars()
+            .next_back()
+            .map_or(true, |c| !is_symbol_char(c));
+        if i < copied || !begins_symbol {
+            continue;
+        }
+        restored.push_str(&source[copied..i]);
+        copied = i + prefix.len();
+        if copied <= start {
+            new_start -= prefix.len();
+        }
+        if copied <= end {
+            new_end -= prefix.len();
+        }
+    }
+    restored.push_str(&source[copied..]);
+
+    if copied != 0 {
+        error.column = restored[..new_start].chars().count() + 1;
+        error.text = restored[new_start..new_end].to_string();
+        error.source = restored;
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_.$:".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn loader(
+        files: HashMap<&'static str, &'static str>,
+    ) -> impl FnMut(&Path) -> io::Result<String> {
+        move |path| {
+            files
+                .get(path.to_str().unwrap())
+                .map(|s| s.to_string())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+        }
+    }
+
+    #[test]
+    fn localize_test() {
+        assert_eq!("(Mult$.loop)", localize("(.loop)", "Mult"));
+        assert_eq!(
+            "  @Mult$.loop // .loop",
+            localize("  @.loop // .loop", "Mult")
+        );
+        assert_eq!("@Main.main", localize("@Main.main", "Mult"));
+        assert_eq!(".equ Mult$.N 2", localize(".equ .N 2", "Mult"));
+        assert_eq!("@'.'", localize("@'.'", "Mult"));
+    }
+
+    #[test]
+    fn link_test() {
+        let mut files = HashMap::new();
+        files.insert(
+            "main.asm",
+            "(.loop)\n.include \"lib/mult.asm\"\n@.loop\n0;JMP",
+        );
+        files.insert("lib/mult.asm", "(MULT)\n(.loop)\n@.loop");
+        files.insert("other/mult.asm", "(.loop)");
+
+        let paths = vec![PathBuf::from("main.asm"), PathBuf::from("other/mult.asm")];
+        let linked = link(&paths, loader(files)).unwrap();
+
+        assert_eq!(
+            "(main$.loop)\n\
+             \n\
+             (MULT)\n\
+             (mult$.loop)\n\
+             @mult$.loop\n\
+             @main$.loop\n\
+             0;JMP\n\
+             (mult_1$.loop)\n",
+            linked.text
+        );
+        assert_eq!(
+            vec!["main.asm", "lib/mult.asm", "other/mult.asm"],
+            linked.files
+        );
+        assert_eq!(
+            vec![
+                (0, 1),
+                (0, 2),
+                (1, 1),
+                (1, 2),
+                (1, 3),
+                (0, 3),
+                (0, 4),
+                (2, 1)
+            ],
+            linked.origins
+        );
+
+        let error = AssembleError::new(ErrorKind::InvalidSymbol, 5, "@mult$.loop", 1..11);
+        let error = linked.locate(error);
+        assert_eq!("lib/mult.asm", error.file);
+        assert_eq!(3, error.line);
+        assert_eq!(
+            (".loop", 2, "@.loop"),
+            (error.text.as_str(), error.column, error.source.as_str())
+        );
+
+        let source = "@main$.a+main$.b+main$.c // x";
+        let error = AssembleError::new(ErrorKind::UndefinedSymbol, 1, source, 9..16);
+        let error = linked.locate(error);
+        assert_eq!((".b", 5), (error.text.as_str(), error.column));
+        let error = linked.locate(AssembleError::new(
+            ErrorKind::UndefinedSymbol,
+            1,
+            source,
+            1..29,
+        ));
+        assert_eq!(".a+.b+.c // x", error.text);
+    }
+
+    #[test]
+    fn link_error_test() {
+        let mut files = HashMap::new();
+        files.insert(
+            "main.asm",
+            ".include \"a.asm\"\n.include \"none.asm\"\n.include a.asm",
+        );
+        files.insert("a.asm", ".include \"main.asm\"");
+
+        let errors = link(&[PathBuf::from("main.asm")], loader(files)).unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.file.as_str(), e.line, e.text.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a.asm", 1, "\"main.asm\""),
+                ("main.asm", 2, "\"none.asm\""),
+                ("main.asm", 3, ".include"),
+            ],
+            kinds
+        );
+        assert!(matches!(errors[0].kind, ErrorKind::RecursiveInclude));
+        assert!(matches!(errors[1].kind, ErrorKind::Io(_)));
+        assert!(matches!(errors[2].kind, ErrorKind::MalformedDirective));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::ast::Instruction;
+use crate::Program;
+use std::io;
+use std::io::prelude::*;
+
+/// Write the listing of the assembled program.
+///
+/// Each source line is prefixed by the ROM address, the encoded word in binary and hex, and the
+/// line number. Labels show the ROM address they resolve to, and the other lines such as comments
+/// are printed with the blank prefix. The instructions generated by a macro invocation follow the
+/// invocation line.
+pub fn write_listing<W: Write>(src: &str, program: &Program, dst: &mut W) -> io::Result<()> {
+    writeln!(
+        dst,
+        "{:>5}  {:<16}  {:<4}  {:>5}  SOURCE",
+        "ROM", "BINARY", "HEX", "LINE"
+    )?;
+
+    let mut address = 0;
+    let mut index = 0;
+    for (i, source) in src.lines().enumerate() {
+        let line = i + 1;
+
+        if program.lines.get(index) != Some(&line) {
+            writeln!(
+                dst,
+                "{:>5}  {:16}  {:4}  {:>5}  {}",
+                "", "", "", line, source
+            )?;
+            continue;
+        }
+
+        let mut is_first = true;
+        while program.lines.get(index) == Some(&line) {
+            let instruction = &program.instructions[index];
+            let (line, source) = if is_first {
+                (line.to_string(), source.to_string())
+            } else {
+                (String::new(), format!("+ {}", instruction))
+            };
+
+            match instruction {
+                Instruction::Label { .. } => {
+                    writeln!(
+                        dst,
+                        "{:>5}  {:16}  {:4}  {:>5}  {}",
+                        address, "", "", line, source
+                    )?;
+                }
+                _ => {
+                    let word = program.words[address];
+                    writeln!(
+                        dst,
+                        "{:>5}  {:016b}  {:04X}  {:>5}  {}",
+                        address, word, word, line, source
+                    )?;
+                    address += 1;
+                }
+            }
+            index += 1;
+            is_first = false;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble_program;
+
+    #[test]
+    fn write_listing_test() {
+        let src = "// Count up.\n\
+                   @i\n\
+                   (LOOP)\n\
+                   \x20   M=M+1 // i++\n\
+                   \n\
+                   \x20   @LOOP\n\
+                   \x20   0;JMP";
+        let program = assemble_program(src).unwrap();
+        let mut output = Vec::<u8>::new();
+        write_listing(src, &program, &mut output).unwrap();
+
+        assert_eq!(
+            "  ROM  BINARY            HEX    LINE  SOURCE\n\
+             \x20                                  1  // Count up.\n\
+             \x20   0  0000000000010000  0010      2  @i\n\
+             \x20   1                              3  (LOOP)\n\
+             \x20   1  1111110111001000  FDC8      4      M=M+1 // i++\n\
+             \x20                                  5  \n\
+             \x20   2  0000000000000001  0001      6      @LOOP\n\
+             \x20   3  1110101010000111  EA87      7      0;JMP\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn write_listing_macro_test() {
+        let src = ".macro INC addr\n\
+                       @addr\n\
+                       M=M+1\n\
+                   .endm\n\
+                   INC i // i++";
+        let program = assemble_program(src).unwrap();
+        let mut output = Vec::<u8>::new();
+        write_listing(src, &program, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().skip(5).collect();
+        assert_eq!(
+            vec![
+                "    0  0000000000010000  0010      5  INC i // i++",
+                "    1  1111110111001000  FDC8         + M=M+1",
+            ],
+            lines
+        );
+    }
+}
@@ -0,0 +1,495 @@
+use crate::error::{AssembleError, ErrorKind};
+use crate::parser;
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::ops::Range;
+
+/// The limit of nested macro invocations to detect recursive macros.
+const MAX_DEPTH: usize = 32;
+
+/// The source after expanding the macros.
+#[derive(Debug)]
+pub struct Expansion {
+    pub text: String,
+    /// 1-origin line numbers in the original source for each line of `text`.
+    pub origins: Vec<usize>,
+}
+
+impl Expansion {
+    /// Convert a line number in the expanded text into the one in the original source.
+    /// The lines generated by a macro invocation are mapped to the invocation.
+    pub fn origin(&self, line: usize) -> usize {
+        line.checked_sub(1)
+            .and_then(|i| self.origins.get(i))
+            .cloned()
+            .unwrap_or(line)
+    }
+}
+
+#[derive(Debug)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+    /// Labels defined in the body. They are renamed in each expansion.
+    labels: Vec<String>,
+}
+
+/// Expand the macros defined by `.macro NAME params ... .endm`.
+///
+/// A macro is invoked by its name followed by the arguments separated by commas or spaces.
+/// The parameters in the body are replaced with the arguments, and the labels defined in the
+/// body are renamed to `NAME.N$LABEL` so that each expansion has its own labels.
+/// The lines of the definitions are left as empty lines to keep the line numbers.
+pub fn expand(src: &str) -> Result<Expansion, Vec<AssembleError>> {
+    let mut expander = Expander::default();
+    for (i, line) in src.lines().enumerate() {
+        expander.feed(line, i + 1);
+    }
+
+    expander.finish()
+}
+
+/// The macro expansion which is fed line by line.
+#[derive(Debug, Default)]
+pub struct Expander {
+    macros: HashMap<String, Macro>,
+    /// The number of expansions to make the labels unique.
+    counter: usize,
+    text: String,
+    origins: Vec<usize>,
+    errors: Vec<AssembleError>,
+    /// The line number and the line of `.macro`, and the body read so far.
+    definition: Option<(usize, String, Vec<String>)>,
+}
+
+impl Expander {
+    /// Expand the line of the given 1-origin line number.
+    pub fn feed(&mut self, line: &str, line_number: usize) {
+        if let Some((_, _, body)) = self.definition.as_mut() {
+            if first_token(line) != Some(".endm") {
+                body.push(line.to_string());
+                return;
+            }
+
+            let (begin, header, body) = self.definition.take().unwrap();
+            let body: Vec<&str> = body.iter().map(String::as_str).collect();
+            self.define(begin, &header, &tokenize(&header, 2), &body);
+            for k in 0..body.len() + 2 {
+                self.push("", begin + k);
+            }
+            return;
+        }
+
+        let tokens = tokenize(line, 1);
+        match tokens.first().map(|range| &line[range.clone()]) {
+            Some(".macro") => self.definition = Some((line_number, line.to_string(), Vec::new())),
+            Some(".endm") => {
+                let range = tokens[0].clone();
+                self.error(ErrorKind::MalformedDirective, line_number, line, range);
+            }
+            _ => self.expand_line(line, line_number, 0),
+        }
+    }
+
+    /// Take the text expanded so far. The origins of the lines are kept.
+    pub fn take_text(&mut self) -> String {
+        mem::take(&mut self.text)
+    }
+
+    /// Finish the expansion after all lines are fed.
+    pub fn finish(mut self) -> Result<Expansion, Vec<AssembleError>> {
+        if let Some((line_number, line, _)) = self.definition.take() {
+            let range = tokenize(&line, 1)[0].clone();
+            self.error(ErrorKind::UnterminatedMacro, line_number, &line, range);
+        }
+
+        if self.errors.is_empty() {
+            Ok(Expansion {
+                text: self.text,
+                origins: self.origins,
+            })
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn push(&mut self, line: &str, origin: usize) {
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.origins.push(origin);
+    }
+
+    fn error(&mut self, kind: ErrorKind, line_number: usize, line: &str, range: Range<usize>) {
+        self.errors
+            .push(AssembleError::new(kind, line_number, line, range));
+    }
+
+    fn define(&mut self, line_number: usize, line: &str, tokens: &[Range<usize>], body: &[&str]) {
+        let name_range = match tokens.get(1) {
+            Some(range) => range.clone(),
+            None => {
+                let range = tokens[0].clone();
+                return self.error(ErrorKind::MalformedDirective, line_number, line, range);
+            }
+        };
+
+        let name = &line[name_range.clone()];
+        if !parser::is_symbol(name) {
+            return self.error(ErrorKind::InvalidSymbol, line_number, line, name_range);
+        }
+        if self.macros.contains_key(name) {
+            return self.error(ErrorKind::DuplicateLabel, line_number, line, name_range);
+        }
+
+        let mut params = Vec::new();
+        for range in tokens[2..].iter() {
+            let param = &line[range.clone()];
+            if !parser::is_symbol(param) {
+                return self.error(ErrorKind::InvalidSymbol, line_number, line, range.clone());
+            }
+            params.push(param.to_string());
+        }
+
+        for (i, body_line) in body.iter().enumerate() {
+            if first_token(body_line) == Some(".macro") {
+                let range = tokenize(body_line, 1)[0].clone();
+                let line_number = line_number + i + 1;
+                return self.error(ErrorKind::MalformedDirective, line_number, body_line, range);
+            }
+        }
+
+        let labels = body
+            .iter()
+            .filter_map(|line| {
+                let code = code_of(line).trim();
+                if code.starts_with('(') && code.ends_with(')') && code.len() >= 2 {
+                    Some(code[1..code.len() - 1].trim().to_string())
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        self.macros.insert(
+            name.to_string(),
+            Macro {
+                params,
+                body: body.iter().map(|line| line.to_string()).collect(),
+                labels,
+            },
+        );
+    }
+
+    fn expand_line(&mut self, line: &str, origin: usize, depth: usize) {
+        let tokens = tokenize(line, 1);
+        let name = match tokens.first().map(|range| &line[range.clone()]) {
+            Some(name) if self.macros.contains_key(name) => name,
+            _ => return self.push(line, origin),
+        };
+
+        if depth >= MAX_DEPTH {
+            let range = tokens[0].clone();
+            return self.error(ErrorKind::RecursiveMacro, origin, line, range);
+        }
+
+        let args: Vec<&str> = tokens[1..]
+            .iter()
+            .map(|range| &line[range.clone()])
+            .collect();
+        let m = &self.macros[name];
+        if args.len() != m.params.len() {
+            let range = tokens[0].start..tokens[tokens.len() - 1].end;
+            return self.error(ErrorKind::MacroArgumentMismatch, origin, line, range);
+        }
+
+        let mut replacements: HashMap<&str, String> = m
+            .params
+            .iter()
+            .map(String::as_str)
+            .zip(args.iter().map(|arg| arg.to_string()))
+            .collect();
+        for label in m.labels.iter() {
+            replacements.insert(label, format!("{}.{}${}", name, self.counter, label));
+        }
+
+        let lines: Vec<String> = m
+            .body
+            .iter()
+            .map(|line| substitute(line, &replacements))
+            .collect();
+        self.counter += 1;
+
+        for line in lines {
+            self.expand_line(&line, origin, depth + 1);
+        }
+    }
+}
+
+/// A reader which expands the macros in the lines read from the inner reader.
+///
+/// The source is read only once, so it can be stdin.
+pub struct Expanding<R> {
+    src: R,
+    expander: Expander,
+    buffer: Vec<u8>,
+    position: usize,
+    line_number: usize,
+}
+
+impl<R: BufRead> Expanding<R> {
+    pub fn new(src: R) -> Self {
+        Self {
+            src,
+            expander: Expander::default(),
+            buffer: Vec::new(),
+            position: 0,
+            line_number: 0,
+        }
+    }
+
+    /// Finish the expansion. The text of the result is empty since it is already read.
+    pub fn finish(self) -> Result<Expansion, Vec<AssembleError>> {
+        self.expander.finish()
+    }
+}
+
+impl<R: BufRead> Read for Expanding<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Expanding<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // A line may expand to nothing such as a line in a macro definition.
+        while self.position == self.buffer.len() {
+            let mut line = String::new();
+            if self.src.read_line(&mut line)? == 0 {
+                break;
+            }
+
+            self.line_number += 1;
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            self.expander.feed(line, self.line_number);
+            self.buffer = self.expander.take_text().into_bytes();
+            self.position = 0;
+        }
+
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt;
+    }
+}
+
+/// The part of the line without the comment.
+fn code_of(line: &str) -> &str {
+    match line.find("//") {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+fn first_token(line: &str) -> Option<&str> {
+    code_of(line).split_whitespace().next()
+}
+
+/// Split the line into the leading words and the arguments.
+/// The arguments are separated by commas if any, otherwise by whitespaces.
+fn tokenize(line: &str, words: usize) -> Vec<Range<usize>> {
+    let code = code_of(line);
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    while tokens.len() < words {
+        let begin = match code[offset..].find(|c: char| !c.is_whitespace()) {
+            Some(i) => offset + i,
+            None => return tokens,
+        };
+        offset = code[begin..]
+            .find(char::is_whitespace)
+            .map_or(code.len(), |i| begin + i);
+        tokens.push(begin..offset);
+    }
+
+    let rest = &code[offset..];
+    let separator = |c: char| {
+        if rest.contains(',') {
+            c == ','
+        } else {
+            c.is_whitespace()
+        }
+    };
+
+    for part in rest.split(separator) {
+        let trimmed = part.trim();
+        if !trimmed.is_empty() {
+            let start = offset + part.find(trimmed).unwrap();
+            tokens.push(start..start + trimmed.len());
+        }
+        offset += part.len() + 1;
+    }
+
+    tokens
+}
+
+/// Replace the symbols in the line outside of the comment and character literals.
+fn substitute(line: &str, replacements: &HashMap<&str, String>) -> String {
+    let code = code_of(line);
+    let mut result = String::with_capacity(line.len());
+    let mut symbol = String::new();
+    let mut in_quote = false;
+
+    let is_symbol_char = |c: char| c.is_ascii_alphanumeric() || "_.$:".contains(c);
+    for c in code.chars() {
+        if !in_quote && is_symbol_char(c) {
+            symbol.push(c);
+            continue;
+        }
+
+        result.push_str(replacements.get(symbol.as_str()).unwrap_or(&symbol));
+        symbol.clear();
+        if c == '\'' {
+            in_quote = !in_quote;
+        }
+        result.push(c);
+    }
+    result.push_str(replacements.get(symbol.as_str()).unwrap_or(&symbol));
+    result.push_str(&line[code.len()..]);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn expand_test() {
+        let src = ".macro PUSH_D\n\
+                   \x20   @SP\n\
+                   \x20   A=M\n\
+                   \x20   M=D\n\
+                   \x20   @SP\n\
+                   \x20   M=M+1\n\
+                   .endm\n\
+                   .macro PUSH_CONST value // push a constant\n\
+                   \x20   @value\n\
+                   \x20   D=A\n\
+                   \x20   PUSH_D\n\
+                   .endm\n\
+                   PUSH_CONST 7\n\
+                   PUSH_CONST 'A'\n\
+                   D=A";
+        let expansion = expand(src).unwrap();
+        let lines: Vec<_> = expansion.text.lines().map(str::trim).collect();
+
+        assert_eq!(
+            vec![
+                "", "", "", "", "", "", "", "", "", "", "", "", "@7", "D=A", "@SP", "A=M", "M=D",
+                "@SP", "M=M+1", "@'A'", "D=A", "@SP", "A=M", "M=D", "@SP", "M=M+1", "D=A",
+            ],
+            lines
+        );
+        assert_eq!(12, expansion.origin(12));
+        assert_eq!(13, expansion.origin(13));
+        assert_eq!(13, expansion.origin(19));
+        assert_eq!(14, expansion.origin(20));
+        assert_eq!(15, expansion.origin(27));
+    }
+
+    #[test]
+    fn expand_local_label_test() {
+        let src = ".macro WAIT_KEY\n\
+                   (LOOP)\n\
+                   \x20   @KBD\n\
+                   \x20   D=M\n\
+                   \x20   @LOOP\n\
+                   \x20   D;JEQ\n\
+                   .endm\n\
+                   (LOOP)\n\
+                   WAIT_KEY\n\
+                   WAIT_KEY\n\
+                   @LOOP";
+        let expansion = expand(src).unwrap();
+        let lines: Vec<_> = expansion.text.lines().map(str::trim).skip(7).collect();
+
+        assert_eq!(
+            vec![
+                "(LOOP)",
+                "(WAIT_KEY.0$LOOP)",
+                "@KBD",
+                "D=M",
+                "@WAIT_KEY.0$LOOP",
+                "D;JEQ",
+                "(WAIT_KEY.1$LOOP)",
+                "@KBD",
+                "D=M",
+                "@WAIT_KEY.1$LOOP",
+                "D;JEQ",
+                "@LOOP",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn expand_error_test() {
+        let src = ".macro\n\
+                   .endm\n\
+                   .macro SET addr, value\n\
+                   \x20   @value\n\
+                   .endm\n\
+                   .endm\n\
+                   SET R0\n\
+                   .macro REC\n\
+                   \x20   REC\n\
+                   .endm\n\
+                   REC\n\
+                   .macro OPEN";
+        let errors = expand(src).unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.text.as_str(), e.kind.to_string()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (1, ".macro", ErrorKind::MalformedDirective.to_string()),
+                (6, ".endm", ErrorKind::MalformedDirective.to_string()),
+                (7, "SET R0", ErrorKind::MacroArgumentMismatch.to_string()),
+                (11, "REC", ErrorKind::RecursiveMacro.to_string()),
+                (12, ".macro", ErrorKind::UnterminatedMacro.to_string()),
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn expanding_test() {
+        let src = ".macro INC addr\n\
+                   \x20   @addr\n\
+                   \x20   M=M+1\n\
+                   .endm\n\
+                   INC i\n\
+                   D=M\n";
+        let mut expanding = Expanding::new(Cursor::new(src));
+        let mut text = String::new();
+        expanding.read_to_string(&mut text).unwrap();
+
+        let lines: Vec<_> = text.lines().map(str::trim).collect();
+        assert_eq!(vec!["", "", "", "", "@i", "M=M+1", "D=M"], lines);
+        assert_eq!(5, expanding.finish().unwrap().origin(6));
+
+        let mut expanding = Expanding::new(Cursor::new(".macro OPEN\n@1"));
+        expanding.read_to_string(&mut text).unwrap();
+        assert_eq!(1, expanding.finish().unwrap_err().len());
+    }
+}
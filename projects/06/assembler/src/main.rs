@@ -1,179 +1,193 @@
-mod code;
-mod parser;
-mod symbol_table;
-
-use parser::{CommandType, Parser};
+use assembler::error::AssembleError;
+use assembler::output::Format;
+use assembler::{disassembler, formatter, listing, optimizer, stream};
 use std::env;
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::io::BufReader;
-use std::io::SeekFrom;
+use std::io::{BufReader, BufWriter};
 use std::io::{Error, ErrorKind};
+use std::mem;
 use std::path::PathBuf;
-use symbol_table::SymbolTable;
+use std::process;
 
-static VARIABLE_ADDRESS_BEGIN: u16 = 16;
+/// The file name shown in the diagnostics for stdin.
+const STDIN_NAME: &str = "<stdin>";
 
-fn main() -> Result<(), std::io::Error> {
-    let src_path = env::args()
-        .nth(1)
-        .ok_or_else(|| Error::new(ErrorKind::NotFound, "No argument"))
-        .map(PathBuf::from)?;
-
-    let mut src = File::open(src_path.as_path()).map(BufReader::new)?;
-    let mut dst = File::create(src_path.with_extension("hack"))?;
-    assemble(&mut src, &mut dst)
-}
+const USAGE: &str = "\
+usage: assembler [OPTIONS] [FILE.asm...|-]
+       assembler -d FILE.hack
+       assembler fmt [--canonical] [FILE.asm...]
 
-fn assemble<R: BufRead + Seek, W: Write>(src: &mut R, dst: &mut W) -> Result<(), std::io::Error> {
-    let mut symbol_table = SymbolTable::new();
+options:
+  -d, --disassemble      print the assembly of the .hack file
+  -l, --listing          write the listing into FILE.lst
+  -s, --symbols          write the symbol table into FILE.sym
+  -O, --optimize         shrink the program by peephole optimizations
+  -f, --format FORMAT    write the program in hack, bin or hex
+  -h, --help             print this message
 
-    pass1(src, &mut symbol_table)?;
+The source is read from stdin and the program is written to stdout without FILE or with `-`.";
 
-    src.seek(SeekFrom::Start(0))?;
+fn main() -> Result<(), std::io::Error> {
+    if env::args().nth(1).as_deref() == Some("fmt") {
+        let (flags, paths): (Vec<_>, Vec<_>) =
+            env::args().skip(2).partition(|arg| arg == "--canonical");
+        return format_files(paths.iter().map(PathBuf::from).collect(), !flags.is_empty());
+    }
 
-    pass2(src, dst, &mut symbol_table)
-}
+    let mut src_paths = Vec::new();
+    let mut disassemble = false;
+    let mut emit_listing = false;
+    let mut emit_symbols = false;
+    let mut optimize = false;
+    let mut format = Format::Hack;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--disassemble" => disassemble = true,
+            "-l" | "--listing" => emit_listing = true,
+            "-s" | "--symbols" => emit_symbols = true,
+            "-O" | "--optimize" => optimize = true,
+            "-f" | "--format" => {
+                format = args.next().and_then(|f| f.parse().ok()).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "format must be hack, bin or hex")
+                })?
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                eprintln!("error: unknown option `{}`\n\n{}", arg, USAGE);
+                process::exit(1);
+            }
+            _ => src_paths.push(PathBuf::from(arg)),
+        }
+    }
 
-/// Read the all lines in order to create symbol table.
-fn pass1<R: BufRead>(src: &mut R, symbol_table: &mut SymbolTable) -> Result<(), std::io::Error> {
-    let mut current_address = 0;
-    let mut parser = Parser::new(src);
-
-    while parser.has_more_commands() {
-        if parser.command_type() == CommandType::Label {
-            // Record the label.
-            symbol_table.add_entry(parser.symbol(), current_address);
-        } else {
-            current_address += 1;
+    // Read stdin and write stdout to be used in pipes.
+    if src_paths.is_empty() || src_paths == [PathBuf::from("-")] {
+        return assemble_stdin(disassemble, optimize, format, emit_listing || emit_symbols);
+    }
+
+    let src_path = src_paths[0].clone();
+    let file_name = src_path.to_string_lossy();
+
+    if disassemble {
+        let mut src = File::open(src_path.as_path()).map(BufReader::new)?;
+        let mut codes = Vec::new();
+        if let Err(errors) = disassembler::disassemble(&mut src, &mut codes) {
+            report_errors("disassemble", &file_name, errors);
         }
 
-        parser.advance();
+        // Print to stdout not to overwrite the original assembly.
+        return std::io::stdout().write_all(&codes);
+    }
+
+    // The output files are named after the first file.
+    let (linked, mut program) =
+        match assembler::assemble_files(&src_paths, |path| fs::read_to_string(path)) {
+            Ok(result) => result,
+            Err(errors) => report_errors("assemble", &file_name, errors),
+        };
+
+    if optimize {
+        program = optimizer::optimize(program);
+    }
+
+    for warning in mem::take(&mut program.warnings) {
+        eprintln!("{}\n", warning.in_file(&file_name));
+    }
+
+    let mut dst = File::create(src_path.with_extension(format.extension())).map(BufWriter::new)?;
+    format.write(&program.words, &mut dst)?;
+    dst.flush()?;
+
+    if emit_listing {
+        let mut dst = File::create(src_path.with_extension("lst"))?;
+        listing::write_listing(&linked.text, &program, &mut dst)?;
+    }
+
+    if emit_symbols {
+        let mut dst = File::create(src_path.with_extension("sym"))?;
+        program.symbol_table.write_sym(&mut dst)?;
     }
 
     Ok(())
 }
 
-/// Generate codes.
-fn pass2<R: BufRead, W: Write>(
-    src: &mut R,
-    dst: &mut W,
-    symbol_table: &mut SymbolTable,
-) -> Result<(), std::io::Error> {
-    let mut parser = Parser::new(src);
-    let mut var_address = VARIABLE_ADDRESS_BEGIN;
-
-    while parser.has_more_commands() {
-        match parser.command_type() {
-            CommandType::Address => {
-                let symbol = parser.symbol();
-                let n = if let Ok(n) = symbol.parse::<u16>() {
-                    // Constant.
-                    n
-                } else if let Some(n) = symbol_table.get_address(&symbol) {
-                    // Use existing variable or label.
-                    n
-                } else {
-                    // Allocate new variable.
-                    symbol_table.add_entry(symbol, var_address);
-
-                    let n = var_address;
-                    var_address += 1;
-                    n
-                };
-
-                dst.write_all(format!("0{:015b}\n", n).as_bytes())?;
-            }
-            CommandType::Compute => {
-                let comp = code::comp(parser.comp());
-                let dest = code::dest(parser.dest());
-                let jump = code::jump(parser.jump());
-                let _ = dst.write(format!("111{:}{:}{:}\n", comp, dest, jump).as_bytes())?;
-            }
-            CommandType::Label => {
-                // Nothing to do.
-            }
-        }
+/// Rewrite the files in place, or format stdin into stdout if no file is given.
+/// The mnemonics are rewritten into the canonical ones if `canonical` is set.
+fn format_files(paths: Vec<PathBuf>, canonical: bool) -> Result<(), std::io::Error> {
+    if paths.is_empty() {
+        let mut src = String::new();
+        io::stdin().read_to_string(&mut src)?;
+        return io::stdout().write_all(formatter::format(&src, canonical).as_bytes());
+    }
 
-        parser.advance();
+    for path in paths {
+        let src = fs::read_to_string(&path)?;
+        let formatted = formatter::format(&src, canonical);
+        if formatted != src {
+            fs::write(&path, formatted)?;
+        }
     }
 
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
-    use std::str;
-
-    #[test]
-    fn assemble_test() {
-        let input: &[u8] = b"@2\n\
-                             D=A\n\
-                             @3\n\
-                             D=D+A\n\
-                             @0\n\
-                             M=D";
-        let mut input = Cursor::new(input);
-        let mut output = Vec::<u8>::new();
-
-        assert_eq!(true, assemble(&mut input, &mut output).is_ok());
-        assert_eq!(
-            "0000000000000010\n\
-             1110110000010000\n\
-             0000000000000011\n\
-             1110000010010000\n\
-             0000000000000000\n\
-             1110001100001000\n",
-            str::from_utf8(&output).unwrap()
-        );
+fn assemble_stdin(
+    disassemble: bool,
+    optimize: bool,
+    format: Format,
+    needs_file: bool,
+) -> Result<(), std::io::Error> {
+    if needs_file {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "listing and symbols need a source file",
+        ));
+    }
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut dst = BufWriter::new(stdout.lock());
+    if disassemble {
+        if let Err(errors) = disassembler::disassemble(&mut stdin.lock(), &mut dst) {
+            report_errors("disassemble", STDIN_NAME, errors);
+        }
+        return dst.flush();
+    }
+
+    let mut program = match stream::assemble_stream(stdin.lock()) {
+        Ok(program) => program,
+        Err(errors) => report_errors("assemble", STDIN_NAME, errors),
+    };
+    if optimize {
+        program = optimizer::optimize(program);
+    }
+
+    for warning in mem::take(&mut program.warnings) {
+        eprintln!("{}\n", warning.in_file(STDIN_NAME));
     }
 
-    #[test]
-    fn assemble_with_label_test() {
-        let input: &[u8] = b"@R0\n\
-                             D=M              // D = first number\n\
-                             @R1\n\
-                             D=D-M            // D = first number - second number\n\
-                             @OUTPUT_FIRST\n\
-                             D;JGT            // if D>0 (first is greater) goto output_first\n\
-                             @R1\n\
-                             D=M              // D = second number\n\
-                             @OUTPUT_D\n\
-                             0;JMP            // goto output_d\n\
-                          (OUTPUT_FIRST)\n\
-                             @R0             \n\
-                             D=M              // D = first number\n\
-                          (OUTPUT_D)\n\
-                             @R2\n\
-                             M=D              // M[2] = D (greatest number)\n\
-                          (INFINITE_LOOP)\n\
-                             @INFINITE_LOOP\n\
-                             0;JMP            // infinite loop";
-
-        let mut input = Cursor::new(input);
-        let mut output = Vec::<u8>::new();
-
-        assert_eq!(true, assemble(&mut input, &mut output).is_ok());
-        assert_eq!(
-            "0000000000000000\n\
-             1111110000010000\n\
-             0000000000000001\n\
-             1111010011010000\n\
-             0000000000001010\n\
-             1110001100000001\n\
-             0000000000000001\n\
-             1111110000010000\n\
-             0000000000001100\n\
-             1110101010000111\n\
-             0000000000000000\n\
-             1111110000010000\n\
-             0000000000000010\n\
-             1110001100001000\n\
-             0000000000001110\n\
-             1110101010000111\n",
-            str::from_utf8(&output).unwrap()
-        );
+    format.write(&program.words, &mut dst)?;
+    dst.flush()
+}
+
+fn report_errors(action: &str, file_name: &str, errors: Vec<AssembleError>) -> ! {
+    let count = errors.len();
+    for error in errors {
+        eprintln!("{}\n", error.in_file(file_name));
     }
+    eprintln!(
+        "error: could not {} `{}` due to {} previous error{}",
+        action,
+        file_name,
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    process::exit(1);
 }
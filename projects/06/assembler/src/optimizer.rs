@@ -0,0 +1,417 @@
+use crate::ast::{Instruction, Value};
+use crate::code::{self, Comp, Dest, Jump};
+use crate::error::ErrorKind;
+use crate::expression;
+use crate::symbol_table::{SymbolKind, SymbolTable};
+use crate::{Program, ROM_SIZE};
+use std::collections::{HashMap, HashSet};
+
+/// The limit of jumps followed in a chain of jumps to jumps.
+const MAX_THREADING: usize = 16;
+
+/// An instruction and its line number in the source.
+type Item = (Instruction, usize);
+
+/// Shrink the assembled program by peephole optimizations.
+///
+/// - `@X` is dropped if A already holds X.
+/// - `D=M` and `M=D` are dropped if D already equals M.
+/// - The code after an unconditional jump is dropped until a referenced label.
+/// - A jump to `@L2; 0;JMP` is redirected to L2.
+///
+/// The code must refer to ROM addresses only by labels, since the addresses change. The program
+/// is returned as it is if it jumps to a constant address such as `@133; 0;JMP`, or if an
+/// expression or a constant depends on a label such as `@LOOP+2` or `.equ RET END`.
+pub fn optimize(mut program: Program) -> Program {
+    if refers_rom_address(&program) {
+        return program;
+    }
+
+    let mut items: Vec<Item> = program
+        .instructions
+        .into_iter()
+        .zip(program.lines)
+        .collect();
+    loop {
+        let len = items.len();
+        let threaded = thread_jumps(&mut items);
+        items = remove_unreachable(items);
+        items = remove_redundant(items);
+        if !threaded && items.len() == len {
+            break;
+        }
+    }
+
+    let (instructions, lines) = items.into_iter().unzip();
+    program.instructions = instructions;
+    program.lines = lines;
+    program.words = encode(&program.instructions, &mut program.symbol_table);
+    let fits = program.words.len() <= ROM_SIZE;
+    program
+        .warnings
+        .retain(|warning| !(fits && matches!(warning.kind, ErrorKind::RomOverflow)));
+
+    program
+}
+
+/// Check the program refers to ROM addresses otherwise than by labels.
+fn refers_rom_address(program: &Program) -> bool {
+    let instructions = &program.instructions;
+    let jumps_to_constant = instructions.windows(2).any(|w| {
+        matches!(
+            w,
+            [
+                Instruction::Address {
+                    value: Value::Constant(_),
+                    ..
+                },
+                Instruction::Compute { jump: Some(_), .. }
+            ]
+        )
+    });
+    // The constants keep the addresses of the labels at the definitions.
+    let mut label_constants = HashSet::new();
+    for (name, expr) in program.constants.iter() {
+        let depends_on_label = symbols_in(expr).iter().any(|s| {
+            program.symbol_table.get_kind(s) == Some(SymbolKind::Label)
+                || label_constants.contains(s)
+        });
+        if depends_on_label {
+            label_constants.insert(name.clone());
+        }
+    }
+    let uses_label_arithmetic = instructions.iter().any(|instruction| match instruction {
+        Instruction::Address {
+            value: Value::Expression(expr),
+            ..
+        } => symbols_in(expr).iter().any(|s| {
+            program.symbol_table.get_kind(s) == Some(SymbolKind::Label)
+                || label_constants.contains(s)
+        }),
+        Instruction::Address {
+            value: Value::Symbol(s),
+            ..
+        } => label_constants.contains(s),
+        _ => false,
+    });
+
+    jumps_to_constant || uses_label_arithmetic
+}
+
+/// Resolve the labels again and encode the instructions.
+fn encode(instructions: &[Instruction], symbol_table: &mut SymbolTable) -> Vec<u16> {
+    let mut address = 0;
+    for instruction in instructions {
+        match instruction {
+            Instruction::Label { name, .. } => {
+                symbol_table.add_entry(name.clone(), address, SymbolKind::Label)
+            }
+            _ => address += 1,
+        }
+    }
+
+    instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Address { value, .. } => Some(match value {
+                Value::Constant(n) => *n,
+                Value::Symbol(s) => symbol_table.get_address(s).unwrap(),
+                Value::Expression(expr) => {
+                    expression::evaluate(expr, |s| symbol_table.get_address(s)).unwrap()
+                }
+            }),
+            Instruction::Compute {
+                dest, comp, jump, ..
+            } => Some(code::encode(*dest, *comp, *jump)),
+            Instruction::Label { .. } => None,
+        })
+        .collect()
+}
+
+/// The symbols used in the expression.
+fn symbols_in(expr: &str) -> Vec<String> {
+    let mut symbols = Vec::new();
+    let _ = expression::evaluate(expr, |s| {
+        symbols.push(s.to_string());
+        Some(0)
+    });
+    symbols
+}
+
+fn referenced_symbols(items: &[Item]) -> HashSet<String> {
+    items
+        .iter()
+        .flat_map(|(instruction, _)| match instruction {
+            Instruction::Address {
+                value: Value::Symbol(s),
+                ..
+            } => vec![s.clone()],
+            Instruction::Address {
+                value: Value::Expression(expr),
+                ..
+            } => symbols_in(expr),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+fn is_unconditional_jump(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Compute {
+            jump: Some(Jump::Jmp),
+            ..
+        }
+    )
+}
+
+fn writes(dest: Option<Dest>, register: char) -> bool {
+    dest.is_some_and(|dest| dest.mnemonic().contains(register))
+}
+
+/// Drop the instructions after unconditional jumps which no jump can reach.
+fn remove_unreachable(items: Vec<Item>) -> Vec<Item> {
+    let referenced = referenced_symbols(&items);
+    let mut reachable = true;
+
+    items
+        .into_iter()
+        .filter(|(instruction, _)| match instruction {
+            Instruction::Label { name, .. } => {
+                reachable |= referenced.contains(name);
+                true
+            }
+            _ if !reachable => false,
+            _ => {
+                reachable = !is_unconditional_jump(instruction);
+                true
+            }
+        })
+        .collect()
+}
+
+/// Drop `@X` if A holds X, and `D=M` or `M=D` if D equals M.
+fn remove_redundant(items: Vec<Item>) -> Vec<Item> {
+    // The facts are forgotten at labels since other paths may join there.
+    let mut a: Option<Value> = None;
+    let mut d_equals_m = false;
+
+    items
+        .into_iter()
+        .filter(|(instruction, _)| match instruction {
+            Instruction::Label { .. } => {
+                a = None;
+                d_equals_m = false;
+                true
+            }
+            Instruction::Address { value, .. } => {
+                if a.as_ref() == Some(value) {
+                    return false;
+                }
+                a = Some(value.clone());
+                d_equals_m = false;
+                true
+            }
+            Instruction::Compute {
+                dest, comp, jump, ..
+            } => {
+                let is_copy = matches!(
+                    (dest, comp),
+                    (Some(Dest::D), Comp::M) | (Some(Dest::M), Comp::D)
+                );
+                if is_copy && d_equals_m && jump.is_none() {
+                    return false;
+                }
+
+                if writes(*dest, 'A') {
+                    a = None;
+                    d_equals_m = false;
+                } else if writes(*dest, 'D') || writes(*dest, 'M') {
+                    // `MD=...` stores the same value into both.
+                    d_equals_m = is_copy || *dest == Some(Dest::Md);
+                }
+                true
+            }
+        })
+        .collect()
+}
+
+/// Redirect the jumps to `@L2; 0;JMP` to L2. Returns whether any jump is redirected.
+fn thread_jumps(items: &mut [Item]) -> bool {
+    // The index of the instruction after each label.
+    let mut targets = HashMap::new();
+    let mut labels = Vec::new();
+    for (i, (instruction, _)) in items.iter().enumerate() {
+        match instruction {
+            Instruction::Label { name, .. } => labels.push(name.clone()),
+            _ => targets.extend(labels.drain(..).map(|label| (label, i))),
+        }
+    }
+
+    // The label which the code at the label jumps to immediately.
+    let forward = |label: &str| match targets
+        .get(label)
+        .map(|i| (items.get(*i), items.get(i + 1)))
+    {
+        Some((
+            Some((
+                Instruction::Address {
+                    value: Value::Symbol(next),
+                    ..
+                },
+                _,
+            )),
+            Some((
+                Instruction::Compute {
+                    dest: None,
+                    jump: Some(Jump::Jmp),
+                    ..
+                },
+                _,
+            )),
+        )) => Some(next.clone()),
+        _ => None,
+    };
+
+    let mut redirects = Vec::new();
+    for i in 0..items.len().saturating_sub(1) {
+        let label = match &items[i].0 {
+            Instruction::Address {
+                value: Value::Symbol(label),
+                ..
+            } => label,
+            _ => continue,
+        };
+        let (dest, comp, jump) = match &items[i + 1].0 {
+            Instruction::Compute {
+                dest,
+                comp,
+                jump: Some(jump),
+                ..
+            } => (*dest, *comp, *jump),
+            _ => continue,
+        };
+
+        // A keeps the label if the jump is not taken, and it is used by the jump instruction.
+        let keeps_a = jump != Jump::Jmp
+            && !matches!(items.get(i + 2), Some((Instruction::Address { .. }, _)));
+        let uses_a = comp.mnemonic().contains(['A', 'M']) || writes(dest, 'A') || writes(dest, 'M');
+        if keeps_a || uses_a {
+            continue;
+        }
+
+        let mut visited = vec![label.clone()];
+        while let Some(next) = forward(&visited[visited.len() - 1]) {
+            if visited.contains(&next) {
+                // The jumps form an infinite loop which is left as it is.
+                visited.truncate(1);
+                break;
+            }
+            visited.push(next);
+            if visited.len() > MAX_THREADING {
+                break;
+            }
+        }
+        if visited.len() > 1 {
+            redirects.push((i, visited.pop().unwrap()));
+        }
+    }
+
+    let threaded = !redirects.is_empty();
+    for (i, target) in redirects {
+        if let Instruction::Address { value, .. } = &mut items[i].0 {
+            *value = Value::Symbol(target);
+        }
+    }
+
+    threaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble_program;
+
+    fn optimize_str(src: &str) -> (Vec<String>, Vec<u16>) {
+        let program = optimize(assemble_program(src).unwrap());
+        let instructions = program
+            .instructions
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+        (instructions, program.words)
+    }
+
+    #[test]
+    fn remove_redundant_test() {
+        let (instructions, _) = optimize_str(
+            "@SP\nM=M+1\n@SP\nA=M-1\nM=D\n@SP\nD=M\n@SP\nM=D\n@R13\nMD=M+1\nD=M\n(L)\n@L\n@L\nD;JGT",
+        );
+        assert_eq!(
+            vec![
+                "@SP", "M=M+1", "A=M-1", "M=D", "@SP", "D=M", "@R13", "MD=M+1", "(L)", "@L",
+                "D;JGT"
+            ],
+            instructions
+        );
+    }
+
+    #[test]
+    fn remove_unreachable_test() {
+        let (instructions, words) =
+            optimize_str("@END\n0;JMP\nD=A\n@5\n(UNUSED)\nM=D\n(END)\n@END\n0;JMP\nD=M");
+        assert_eq!(
+            vec!["@END", "0;JMP", "(UNUSED)", "(END)", "@END", "0;JMP"],
+            instructions
+        );
+        assert_eq!(vec![2, 0xEA87, 2, 0xEA87], words);
+    }
+
+    #[test]
+    fn thread_jumps_test() {
+        let (instructions, words) = optimize_str(
+            "@A1\nD;JGT\n@R0\nM=D\n@A1\n0;JMP\n(A1)\n@A2\n0;JMP\n(A2)\n@A1\nD;JEQ\n@A2\n0;JMP",
+        );
+        assert_eq!(
+            vec![
+                "@A2", "D;JGT", "@R0", "M=D", "@A2", "0;JMP", "(A1)", "(A2)", "@A2", "D;JEQ",
+                "0;JMP"
+            ],
+            instructions
+        );
+        assert_eq!(6, words[0]);
+
+        // The jumps in a cycle are not redirected.
+        let (instructions, _) = optimize_str("@A1\n0;JMP\n(A1)\n@A2\n0;JMP\n(A2)\n@A1\n0;JMP");
+        assert_eq!(
+            vec!["@A1", "0;JMP", "(A1)", "@A2", "0;JMP", "(A2)", "@A1", "0;JMP"],
+            instructions
+        );
+
+        // A is still used if the jump is not taken.
+        let (instructions, _) = optimize_str("@A1\nD;JGT\nM=D\n(A1)\n@A2\n0;JMP\n(A2)\n@A2\n0;JMP");
+        assert_eq!("@A1", instructions[0]);
+    }
+
+    #[test]
+    fn rom_address_test() {
+        let src = "@END-1\n0;JMP\nD=A\n(END)\n@END\n0;JMP";
+        let program = optimize(assemble_program(src).unwrap());
+        assert_eq!(6, program.instructions.len());
+
+        let src = "@4\n0;JMP\nD=A\n@SP\n@SP\nM=D";
+        let program = optimize(assemble_program(src).unwrap());
+        assert_eq!(6, program.instructions.len());
+
+        // The constants defined by the labels keep the old addresses.
+        let src = "@1\n@1\nD=A\n.equ RET END\n@RET\n0;JMP\n(END)\n@END\n0;JMP";
+        let program = optimize(assemble_program(src).unwrap());
+        assert_eq!(vec![1, 1, 0xEC10, 5, 0xEA87, 5, 0xEA87], program.words);
+        let src = ".equ RET END\n.equ NEXT RET+1\n@1\n@1\n@NEXT-1\n0;JMP\n(END)\n@END\n0;JMP";
+        let program = optimize(assemble_program(src).unwrap());
+        assert_eq!(7, program.instructions.len());
+
+        let program = optimize(assemble_program(".equ N 2\n@N\n@N\nD=A").unwrap());
+        assert_eq!(vec![2, 0xEC10], program.words);
+    }
+}
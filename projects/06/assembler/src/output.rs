@@ -0,0 +1,146 @@
+use std::io;
+use std::io::prelude::*;
+use std::str::FromStr;
+
+/// The number of data bytes in an Intel HEX record.
+const HEX_RECORD_SIZE: usize = 16;
+
+/// The file formats of the assembled program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A line of 16 ASCII digits per word.
+    Hack,
+    /// The raw 16-bit words in big-endian.
+    Binary,
+    /// Intel HEX records of the big-endian words. The addresses count bytes, not words.
+    IntelHex,
+}
+
+impl Format {
+    /// The file extension of the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Hack => "hack",
+            Format::Binary => "bin",
+            Format::IntelHex => "hex",
+        }
+    }
+
+    /// Write the words in the format.
+    pub fn write<W: Write>(self, words: &[u16], dst: &mut W) -> io::Result<()> {
+        match self {
+            Format::Hack => crate::write_hack(words, dst),
+            Format::Binary => write_binary(words, dst),
+            Format::IntelHex => write_intel_hex(words, dst),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hack" => Ok(Format::Hack),
+            "bin" => Ok(Format::Binary),
+            "hex" => Ok(Format::IntelHex),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Write the words as raw big-endian bytes.
+pub fn write_binary<W: Write>(words: &[u16], dst: &mut W) -> io::Result<()> {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    dst.write_all(&bytes)
+}
+
+/// Write the words as Intel HEX data records followed by the end of file record.
+///
+/// The whole ROM of 64K bytes fits in the 16-bit addresses, while the bytes of a program
+/// exceeding the ROM are preceded by an extended linear address record of the upper 16 bits.
+pub fn write_intel_hex<W: Write>(words: &[u16], dst: &mut W) -> io::Result<()> {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let mut upper = 0;
+    for (i, data) in bytes.chunks(HEX_RECORD_SIZE).enumerate() {
+        let address = i * HEX_RECORD_SIZE;
+        if address >> 16 != upper {
+            upper = address >> 16;
+            write_hex_record(dst, 0, 0x04, &(upper as u16).to_be_bytes())?;
+        }
+        write_hex_record(dst, address as u16, 0x00, data)?;
+    }
+
+    write_hex_record(dst, 0, 0x01, &[])
+}
+
+fn write_hex_record<W: Write>(dst: &mut W, address: u16, kind: u8, data: &[u8]) -> io::Result<()> {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&address.to_be_bytes());
+    record.push(kind);
+    record.extend_from_slice(data);
+
+    // The sum of all bytes including the checksum is zero.
+    let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    record.push(sum.wrapping_neg());
+
+    write!(dst, ":")?;
+    for byte in record {
+        write!(dst, "{:02X}", byte)?;
+    }
+    writeln!(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_binary_test() {
+        let mut dst = Vec::new();
+        write_binary(&[0x0002, 0xEA87], &mut dst).unwrap();
+        assert_eq!(vec![0x00, 0x02, 0xEA, 0x87], dst);
+    }
+
+    #[test]
+    fn write_intel_hex_test() {
+        let words: Vec<u16> = (0..9).map(|i| 0x0101 * i).collect();
+        let mut dst = Vec::new();
+        Format::IntelHex.write(&words, &mut dst).unwrap();
+        assert_eq!(
+            ":1000000000000101020203030404050506060707B8\n\
+             :020010000808DE\n\
+             :00000001FF\n",
+            String::from_utf8(dst).unwrap()
+        );
+
+        let mut dst = Vec::new();
+        Format::IntelHex.write(&[], &mut dst).unwrap();
+        assert_eq!(":00000001FF\n", String::from_utf8(dst).unwrap());
+
+        // The words after the ROM are in the next 64K bytes.
+        let mut words = vec![0; 0x8001];
+        words[0x8000] = 0xABCD;
+        let mut dst = Vec::new();
+        Format::IntelHex.write(&words, &mut dst).unwrap();
+        let hex = String::from_utf8(dst).unwrap();
+        let lines: Vec<&str> = hex.lines().collect();
+        assert_eq!(0x1000 + 3, lines.len());
+        assert_eq!(
+            vec![
+                ":10FFF0000000000000000000000000000000000001",
+                ":020000040001F9",
+                ":02000000ABCD86",
+                ":00000001FF"
+            ],
+            lines[0xFFF..]
+        );
+    }
+
+    #[test]
+    fn format_test() {
+        assert_eq!(Ok(Format::Binary), "bin".parse());
+        assert_eq!(Err(()), "elf".parse::<Format>());
+        assert_eq!("hex", Format::IntelHex.extension());
+    }
+}
@@ -1,61 +1,173 @@
+use crate::ast::{Instruction, Span, Value};
+use crate::code::{self, Comp, Dest, Jump};
+use crate::error::{AssembleError, ErrorKind};
+use crate::expression;
 use std::io::BufRead;
+use std::ops::Range;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum CommandType {
     Address,
     Compute,
     Label,
+    /// Assembler directives such as `.equ` which begin with '.'.
+    Directive,
+}
+
+/// Check the given string is a symbol.
+/// A symbol is a sequence of letters, digits, '_', '.', '$' and ':' which does not begin with a digit.
+pub fn is_symbol(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || "_.$:".contains(c) => {
+            chars.all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c))
+        }
+        _ => false,
+    }
+}
+
+/// The largest value which an A-instruction can load.
+pub const MAX_CONSTANT: u32 = 0x7FFF;
+
+/// Parse the given string as a constant.
+///
+/// Decimal (`16384`), hexadecimal (`0x4000`), binary (`0b1010`) and character (`'A'`) forms are
+/// accepted. `None` is returned if the string is not a constant, that is, a symbol.
+pub fn parse_constant(s: &str) -> Option<Result<u16, ErrorKind>> {
+    if let Some(rest) = s.strip_prefix('-') {
+        return parse_constant(rest).map(|result| match result {
+            Ok(0) => Ok(0),
+            Ok(_) => Err(ErrorKind::NegativeConstant),
+            Err(e) => Err(e),
+        });
+    }
+
+    let value = if let Some(c) = s.strip_prefix('\'') {
+        let mut chars = c.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(c), Some('\''), None) => Ok(c as u32),
+            _ => Err(ErrorKind::InvalidConstant),
+        }
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        let (digits, radix) = if let Some(hex) = s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+            (hex, 16)
+        } else if let Some(bin) = s.strip_prefix("0b").or(s.strip_prefix("0B")) {
+            (bin, 2)
+        } else {
+            (s, 10)
+        };
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            Err(ErrorKind::InvalidConstant)
+        } else {
+            // Too long digits are out of range as well.
+            Ok(u32::from_str_radix(digits, radix).unwrap_or(u32::MAX))
+        }
+    } else {
+        return None;
+    };
+
+    Some(value.and_then(|n| {
+        if n <= MAX_CONSTANT {
+            Ok(n as u16)
+        } else {
+            Err(ErrorKind::ConstantOutOfRange)
+        }
+    }))
 }
 
 #[derive(Debug)]
 pub struct Parser<'a, T: BufRead> {
     contents: &'a mut T,
     current_line: String,
+    /// The current line as it is written in the source.
+    raw_line: String,
+    /// Byte offsets in `raw_line` for each byte of `current_line`.
+    offsets: Vec<usize>,
+    line_number: usize,
     has_next: bool,
 }
 
 impl<'a, T: BufRead> Parser<'a, T> {
-    pub fn new(contents: &'a mut T) -> Parser<'a, T> {
+    pub fn new(contents: &'a mut T) -> Result<Parser<'a, T>, AssembleError> {
         let current_line = String::new();
 
         let mut p = Parser {
             contents,
             current_line,
+            raw_line: String::new(),
+            offsets: Vec::new(),
+            line_number: 0,
             has_next: true,
         };
 
-        p.advance();
+        p.advance()?;
 
-        p
+        Ok(p)
     }
 
     pub fn has_more_commands(&self) -> bool {
         self.has_next
     }
 
-    pub fn advance(&mut self) {
+    /// Move to the next command. It does nothing after the last command.
+    pub fn advance(&mut self) -> Result<(), AssembleError> {
         if !self.has_more_commands() {
-            panic!("You cannot call advance if has_more_commands returns false");
+            return Ok(());
         }
 
         loop {
-            self.current_line.clear();
-            let num_bytes = self
-                .contents
-                .read_line(&mut self.current_line)
-                .expect("reading won't fail");
+            self.raw_line.clear();
+            let num_bytes = match self.contents.read_line(&mut self.raw_line) {
+                Ok(n) => n,
+                Err(e) => {
+                    self.current_line.clear();
+                    self.offsets.clear();
+                    return Err(self.error(0..0, ErrorKind::Io(e)));
+                }
+            };
 
             if num_bytes == 0 {
                 // EOF.
+                self.current_line.clear();
+                self.offsets.clear();
                 self.has_next = false;
                 break;
             }
 
+            self.line_number += 1;
+            let trimmed_len = self.raw_line.trim_end_matches(&['\r', '\n'][..]).len();
+            self.raw_line.truncate(trimmed_len);
+
             // Skip empty lines.
-            self.current_line.retain(|c| !c.is_whitespace());
+            self.current_line.clear();
+            self.offsets.clear();
+            // Whitespaces in character literals are kept.
+            // Directives keep a space between the arguments as a separator.
+            let is_directive = self.raw_line.trim_start().starts_with('.');
+            let mut in_quote = false;
+            let mut after_space = false;
+            for (i, c) in self.raw_line.char_indices() {
+                if c == '\'' {
+                    in_quote = !in_quote;
+                }
+                if in_quote || c == '\'' || !c.is_whitespace() {
+                    if after_space && !self.current_line.is_empty() {
+                        self.current_line.push(' ');
+                        self.offsets.push(i - 1);
+                    }
+                    self.current_line.push(c);
+                    self.offsets.extend((0..c.len_utf8()).map(|j| i + j));
+                }
+                after_space = is_directive && !in_quote && c.is_whitespace();
+            }
             match self.current_line.find("//") {
                 Some(0) => continue,
-                Some(i) => self.current_line.truncate(i),
+                Some(i) => {
+                    let i = self.current_line[..i].trim_end().len();
+                    self.current_line.truncate(i);
+                    self.offsets.truncate(i);
+                }
                 None => {}
             }
 
@@ -64,51 +176,211 @@ impl<'a, T: BufRead> Parser<'a, T> {
                 break;
             }
         }
+
+        Ok(())
     }
 
-    pub fn command_type(&self) -> CommandType {
-        match self.current_line.as_bytes()[0] {
-            b'@' => CommandType::Address,
-            b'(' => CommandType::Label,
-            _ => CommandType::Compute,
+    /// 1-origin line number of the current command.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// Create an error pointing the given range of the current command.
+    pub fn error(&self, range: Range<usize>, kind: ErrorKind) -> AssembleError {
+        self.error_at(&self.span(range), kind)
+    }
+
+    /// Create an error pointing the given span of the source line.
+    pub fn error_at(&self, span: &Span, kind: ErrorKind) -> AssembleError {
+        AssembleError::new(kind, self.line_number, &self.raw_line, span.clone())
+    }
+
+    /// Convert the range of the current command into the span of the source line.
+    pub fn span(&self, range: Range<usize>) -> Span {
+        if range.start < range.end {
+            let last = self.offsets[range.end - 1];
+            let last_len = self.raw_line[last..]
+                .chars()
+                .next()
+                .map_or(1, char::len_utf8);
+            self.offsets[range.start]..last + last_len
+        } else {
+            // Point the position just after the previous character if the range is empty.
+            let pos = match range.start.checked_sub(1) {
+                Some(i) if i < self.offsets.len() => {
+                    let prev = self.offsets[i];
+                    prev + self.raw_line[prev..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8)
+                }
+                _ => self.offsets.first().cloned().unwrap_or(0),
+            };
+            pos..pos
         }
     }
 
-    pub fn symbol(&self) -> String {
+    /// Parse the current command into an instruction.
+    ///
+    /// A directive is not an instruction and reported as `InvalidInstruction`.
+    pub fn instruction(&self) -> Result<Instruction, Vec<AssembleError>> {
         match self.command_type() {
             CommandType::Address => {
-                let s = self.current_line.as_str();
-                let end = s.len();
-                s[1..end].to_string()
-            }
-            CommandType::Label => {
-                let s = self.current_line.as_str();
-                let end = s.len();
-                s[1..end - 1].to_string()
+                let range = self.symbol_range();
+                let text = &self.current_line[range.clone()];
+                let value = if expression::is_expression(text) {
+                    Value::Expression(text.to_string())
+                } else if let Some(result) = parse_constant(text) {
+                    Value::Constant(result.map_err(|kind| vec![self.error(range.clone(), kind)])?)
+                } else if is_symbol(text) {
+                    Value::Symbol(text.to_string())
+                } else {
+                    return Err(vec![self.error(range, ErrorKind::InvalidSymbol)]);
+                };
+
+                Ok(Instruction::Address {
+                    value,
+                    span: self.span(range),
+                })
             }
             CommandType::Compute => {
-                panic!("You can call symbol only if the command type is address or label")
+                let comp_range = self.comp_range();
+                let comp = &self.current_line[comp_range.clone()];
+                let comp = Comp::parse(comp).ok_or_else(|| {
+                    let kind = match code::suggest_comp(comp) {
+                        Some(suggestion) => ErrorKind::UnencodableComp(suggestion),
+                        None => ErrorKind::InvalidComp,
+                    };
+                    self.error(comp_range, kind)
+                });
+                let dest = self.dest_range().map(|range| {
+                    Dest::parse(&self.current_line[range.clone()])
+                        .ok_or_else(|| self.error(range, ErrorKind::InvalidDest))
+                });
+                let jump = self.jump_range().map(|range| {
+                    Jump::parse(&self.current_line[range.clone()])
+                        .ok_or_else(|| self.error(range, ErrorKind::InvalidJump))
+                });
+
+                match (comp, dest.transpose(), jump.transpose()) {
+                    (Ok(comp), Ok(dest), Ok(jump)) => Ok(Instruction::Compute {
+                        dest,
+                        comp,
+                        jump,
+                        span: self.span(self.command_range()),
+                    }),
+                    (comp, dest, jump) => {
+                        let mut errors = Vec::new();
+                        errors.extend(dest.err());
+                        errors.extend(comp.err());
+                        errors.extend(jump.err());
+                        Err(errors)
+                    }
+                }
+            }
+            CommandType::Label => {
+                let range = self.symbol_range();
+                let name = &self.current_line[range.clone()];
+                if !self.is_label_closed() {
+                    Err(vec![self.error(0..range.end, ErrorKind::MalformedLabel)])
+                } else if !is_symbol(name) {
+                    Err(vec![self.error(range, ErrorKind::InvalidSymbol)])
+                } else {
+                    Ok(Instruction::Label {
+                        name: name.to_string(),
+                        span: self.span(range),
+                    })
+                }
             }
+            CommandType::Directive => Err(vec![
+                self.error(self.command_range(), ErrorKind::InvalidInstruction)
+            ]),
         }
     }
 
-    pub fn dest(&self) -> Option<String> {
-        if self.command_type() != CommandType::Compute {
-            panic!("You can call dest only if the command type is compute");
+    pub fn command_type(&self) -> CommandType {
+        match self.current_line.bytes().next() {
+            Some(b'@') => CommandType::Address,
+            Some(b'(') => CommandType::Label,
+            Some(b'.') => CommandType::Directive,
+            _ => CommandType::Compute,
         }
+    }
+
+    pub fn command_range(&self) -> Range<usize> {
+        0..self.current_line.len()
+    }
 
-        if let Some(i) = self.current_line.find('=') {
-            Some(self.current_line[0..i].to_string())
+    /// The range of the symbol of the address or the label command.
+    fn symbol_range(&self) -> Range<usize> {
+        let len = self.current_line.len();
+        if self.is_label_closed() {
+            1..len - 1
         } else {
-            None
+            1..len
+        }
+    }
+
+    /// The name of the directive without '.', or empty if the command is not a directive.
+    pub fn directive(&self) -> String {
+        self.current_line[self.directive_range()].to_string()
+    }
+
+    pub fn directive_range(&self) -> Range<usize> {
+        if self.command_type() != CommandType::Directive {
+            return 0..0;
         }
+
+        1..self
+            .current_line
+            .find(' ')
+            .unwrap_or(self.current_line.len())
     }
 
-    pub fn comp(&self) -> String {
-        if self.command_type() != CommandType::Compute {
-            panic!("You can call dest only if the command type is compute");
+    /// The space separated arguments of the directive.
+    pub fn arguments(&self) -> Vec<String> {
+        self.argument_ranges()
+            .into_iter()
+            .map(|range| self.current_line[range].to_string())
+            .collect()
+    }
+
+    pub fn argument_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        if self.command_type() != CommandType::Directive {
+            return ranges;
+        }
+        let mut begin = self.directive_range().end + 1;
+        let mut in_quote = false;
+        for (i, c) in self.current_line.char_indices().skip(begin) {
+            if c == '\'' {
+                in_quote = !in_quote;
+            } else if c == ' ' && !in_quote {
+                ranges.push(begin..i);
+                begin = i + 1;
+            }
+        }
+        if begin < self.current_line.len() {
+            ranges.push(begin..self.current_line.len());
         }
 
+        ranges
+    }
+
+    /// Check the label is closed by ')'.
+    fn is_label_closed(&self) -> bool {
+        self.command_type() == CommandType::Label
+            && self.current_line.len() >= 2
+            && self.current_line.ends_with(')')
+    }
+
+    /// The range of dest of the compute command.
+    fn dest_range(&self) -> Option<Range<usize>> {
+        self.current_line.find('=').map(|i| 0..i)
+    }
+
+    /// The range of comp of the compute command.
+    fn comp_range(&self) -> Range<usize> {
         let i_head = if let Some(i) = self.current_line.find('=') {
             i + 1
         } else {
@@ -121,19 +393,14 @@ impl<'a, T: BufRead> Parser<'a, T> {
             self.current_line.len()
         };
 
-        self.current_line[i_head..i_tail].to_string()
+        i_head..i_tail.max(i_head)
     }
 
-    pub fn jump(&self) -> Option<String> {
-        if self.command_type() != CommandType::Compute {
-            panic!("You can call dest only if the command type is compute");
-        }
-
-        if let Some(i) = self.current_line.find(';') {
-            Some(self.current_line[i + 1..self.current_line.len()].to_string())
-        } else {
-            None
-        }
+    /// The range of jump of the compute command.
+    fn jump_range(&self) -> Option<Range<usize>> {
+        self.current_line
+            .find(';')
+            .map(|i| i + 1..self.current_line.len())
     }
 }
 
@@ -145,72 +412,244 @@ mod tests {
     #[test]
     fn has_more_commands_test() {
         let mut cursor = Cursor::new("D=A");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!(true, parser.has_more_commands());
+        let parser = Parser::new(&mut cursor).unwrap();
+        assert!(parser.has_more_commands());
 
         // Empty input is given.
         let mut cursor = Cursor::new("");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!(false, parser.has_more_commands());
+        let parser = Parser::new(&mut cursor).unwrap();
+        assert!(!parser.has_more_commands());
     }
 
     #[test]
     fn command_type_test() {
         let mut cursor = Cursor::new(b"@999\n(LOOP)\nD=A");
-        let mut parser = Parser::new(&mut cursor);
+        let mut parser = Parser::new(&mut cursor).unwrap();
         assert_eq!(CommandType::Address, parser.command_type());
 
-        parser.advance();
+        parser.advance().unwrap();
         assert_eq!(CommandType::Label, parser.command_type());
 
-        parser.advance();
+        parser.advance().unwrap();
         assert_eq!(CommandType::Compute, parser.command_type());
     }
 
+    fn parse(src: &str) -> Result<Instruction, Vec<AssembleError>> {
+        let mut cursor = Cursor::new(src);
+        Parser::new(&mut cursor).unwrap().instruction()
+    }
+
+    fn parse_ok(src: &str) -> Instruction {
+        parse(src).unwrap()
+    }
+
     #[test]
-    fn symbol_test() {
-        let mut cursor = Cursor::new(b"@999\n(LOOP)\nD=A");
-        let mut parser = Parser::new(&mut cursor);
-        assert_eq!("999", parser.symbol());
+    fn address_test() {
+        let address = |value, span| Instruction::Address { value, span };
+        assert_eq!(address(Value::Constant(999), 1..4), parse_ok("@999"));
+        assert_eq!(
+            address(Value::Constant(0x4000), 3..9),
+            parse_ok("  @0x4000")
+        );
+        assert_eq!(
+            address(Value::Symbol("LOOP".to_string()), 1..5),
+            parse_ok("@LOOP")
+        );
+        assert_eq!(
+            address(Value::Expression("SCREEN+1".to_string()), 2..12),
+            parse_ok("@ SCREEN + 1 // comment")
+        );
+
+        let errors = parse("@1x").unwrap_err();
+        assert!(matches!(errors[0].kind, ErrorKind::InvalidConstant));
+        let errors = parse("@A;B").unwrap_err();
+        assert!(matches!(errors[0].kind, ErrorKind::InvalidSymbol));
+    }
 
-        parser.advance();
-        assert_eq!("LOOP", parser.symbol());
+    #[test]
+    fn label_test() {
+        assert_eq!(
+            Instruction::Label {
+                name: "LOOP".to_string(),
+                span: 1..5
+            },
+            parse_ok("(LOOP)")
+        );
+
+        let errors = parse("(LOOP").unwrap_err();
+        assert!(matches!(errors[0].kind, ErrorKind::MalformedLabel));
+        let errors = parse("(1A)").unwrap_err();
+        assert!(matches!(errors[0].kind, ErrorKind::InvalidSymbol));
     }
 
     #[test]
-    fn dest_test() {
-        let mut cursor = Cursor::new(b"D=A");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!(Some("D".to_string()), parser.dest());
+    fn compute_test() {
+        let compute = |dest, comp, jump, span| Instruction::Compute {
+            dest,
+            comp,
+            jump,
+            span,
+        };
+        assert_eq!(compute(Some(Dest::D), Comp::A, None, 0..3), parse_ok("D=A"));
+        assert_eq!(
+            compute(Some(Dest::Amd), Comp::A, None, 1..7),
+            parse_ok(" AMD =A")
+        );
+        assert_eq!(
+            compute(None, Comp::Zero, Some(Jump::Jmp), 0..5),
+            parse_ok("0;JMP")
+        );
+        assert_eq!(
+            compute(Some(Dest::A), Comp::MMinusOne, None, 0..9),
+            parse_ok("A = M - 1")
+        );
+        assert_eq!(
+            compute(None, Comp::D, Some(Jump::Jeq), 0..7),
+            parse_ok("D ; JEQ ")
+        );
+
+        let errors = parse("X=A+M;JMQ").unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.text.as_str(), e.kind.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("X", ErrorKind::InvalidDest.to_string()),
+                ("A+M", ErrorKind::UnencodableComp("D+M".into()).to_string()),
+                ("JMQ", ErrorKind::InvalidJump.to_string()),
+            ],
+            kinds
+        );
+    }
 
-        let mut cursor = Cursor::new(b" AMD =A");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!(Some("AMD".to_string()), parser.dest());
+    #[test]
+    fn is_symbol_test() {
+        assert!(is_symbol("LOOP"));
+        assert!(is_symbol("Main.main$ret.1"));
+        assert!(is_symbol("_a:b"));
+        assert!(!is_symbol("1abc"));
+        assert!(!is_symbol("A+1"));
+        assert!(!is_symbol(""));
+    }
 
-        let mut cursor = Cursor::new(b"0;JMP");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!(None, parser.dest());
+    #[test]
+    fn parse_constant_test() {
+        assert_eq!(
+            Some(Ok(16384)),
+            parse_constant("16384").map(|r| r.map_err(|_| ()))
+        );
+        assert_eq!(
+            Some(Ok(0x4000)),
+            parse_constant("0x4000").map(|r| r.map_err(|_| ()))
+        );
+        assert_eq!(
+            Some(Ok(0x6000)),
+            parse_constant("0X6000").map(|r| r.map_err(|_| ()))
+        );
+        assert_eq!(
+            Some(Ok(10)),
+            parse_constant("0b1010").map(|r| r.map_err(|_| ()))
+        );
+        assert_eq!(
+            Some(Ok(65)),
+            parse_constant("'A'").map(|r| r.map_err(|_| ()))
+        );
+        assert_eq!(Some(Ok(0)), parse_constant("-0").map(|r| r.map_err(|_| ())));
+        assert_eq!(None, parse_constant("LOOP").map(|r| r.map_err(|_| ())));
+
+        assert!(matches!(
+            parse_constant("-1"),
+            Some(Err(ErrorKind::NegativeConstant))
+        ));
+        assert!(matches!(
+            parse_constant("0x8000"),
+            Some(Err(ErrorKind::ConstantOutOfRange))
+        ));
+        assert!(matches!(
+            parse_constant("0xFFFFFFFFFF"),
+            Some(Err(ErrorKind::ConstantOutOfRange))
+        ));
+        assert!(matches!(
+            parse_constant("0x"),
+            Some(Err(ErrorKind::InvalidConstant))
+        ));
+        assert!(matches!(
+            parse_constant("0b102"),
+            Some(Err(ErrorKind::InvalidConstant))
+        ));
+        assert!(matches!(
+            parse_constant("12ab"),
+            Some(Err(ErrorKind::InvalidConstant))
+        ));
+        assert!(matches!(
+            parse_constant("'AB'"),
+            Some(Err(ErrorKind::InvalidConstant))
+        ));
     }
 
     #[test]
-    fn comp_test() {
-        let mut cursor = Cursor::new(b"0;JMP");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!("0", parser.comp());
+    fn directive_test() {
+        let mut cursor = Cursor::new(b"  .equ   ROW\t SCREEN + 32 // comment\n.endm");
+        let mut parser = Parser::new(&mut cursor).unwrap();
+        assert_eq!(CommandType::Directive, parser.command_type());
+        assert_eq!("equ", parser.directive());
+        assert_eq!(vec!["ROW", "SCREEN", "+", "32"], parser.arguments());
+
+        let ranges = parser.argument_ranges();
+        let error = parser.error(ranges[1].start..ranges[3].end, ErrorKind::InvalidSymbol);
+        assert_eq!("SCREEN + 32", error.text);
+        assert_eq!(15, error.column);
+
+        parser.advance().unwrap();
+        assert_eq!("endm", parser.directive());
+        assert!(parser.arguments().is_empty());
+
+        let errors = parser.instruction().unwrap_err();
+        assert!(matches!(errors[0].kind, ErrorKind::InvalidInstruction));
+        assert_eq!(".endm", errors[0].text);
+    }
 
-        let mut cursor = Cursor::new(b"A = M - 1");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!("M-1", parser.comp());
+    #[test]
+    fn end_test() {
+        let mut cursor = Cursor::new(b"@1 // comment");
+        let mut parser = Parser::new(&mut cursor).unwrap();
+        assert_eq!("", parser.directive());
+        assert!(parser.argument_ranges().is_empty());
+
+        parser.advance().unwrap();
+        assert!(!parser.has_more_commands());
+        parser.advance().unwrap();
+        assert!(!parser.has_more_commands());
+        let errors = parser.instruction().unwrap_err();
+        assert!(matches!(errors[0].kind, ErrorKind::InvalidComp));
+    }
+
+    #[test]
+    fn char_literal_test() {
+        assert_eq!(
+            Instruction::Address {
+                value: Value::Constant(32),
+                span: 4..7
+            },
+            parse_ok("  @ ' '  // space")
+        );
     }
 
     #[test]
-    fn jump_test() {
-        let mut cursor = Cursor::new(b"0;JMP");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!(Some("JMP".to_string()), parser.jump());
-
-        let mut cursor = Cursor::new(b"D ; JEQ ");
-        let parser = Parser::new(&mut cursor);
-        assert_eq!(Some("JEQ".to_string()), parser.jump());
+    fn error_test() {
+        let mut cursor = Cursor::new(b"@1\n  D = M + A  // comment");
+        let mut parser = Parser::new(&mut cursor).unwrap();
+        parser.advance().unwrap();
+
+        let error = parser.instruction().unwrap_err().remove(0);
+        assert_eq!(2, error.line);
+        assert_eq!(7, error.column);
+        assert_eq!("M + A", error.text);
+        assert_eq!("  D = M + A  // comment", error.source);
+
+        let error = parse("D=").unwrap_err().remove(0);
+        assert_eq!(3, error.column);
+        assert_eq!("", error.text);
     }
 }
@@ -0,0 +1,269 @@
+use crate::ast::{Instruction, Value};
+use crate::error::{AssembleError, ErrorKind};
+use crate::parser::{CommandType, Parser};
+use crate::symbol_table::SymbolTable;
+use crate::{code, expression, macros};
+use crate::{Program, ROM_SIZE, VARIABLE_ADDRESS_BEGIN};
+use std::collections::{HashMap, HashSet};
+use std::io::prelude::*;
+
+/// An A-instruction which refers to a symbol not defined yet when it is read.
+struct Fixup {
+    /// The index of the word to patch.
+    index: usize,
+    value: Value,
+    /// The location of the symbol to report the problems.
+    location: AssembleError,
+}
+
+/// Assemble the source in a single pass, so that it can be read from stdin.
+///
+/// The source is parsed only once. The A-instructions referring to the symbols which are not
+/// defined yet, such as forward labels, are emitted as placeholders and patched at the end.
+/// The result is the same as `assemble_program`.
+pub fn assemble_stream<R: BufRead>(src: R) -> Result<Program, Vec<AssembleError>> {
+    let mut expanding = macros::Expanding::new(src);
+    let result = assemble_expanded(&mut expanding);
+    let expansion = expanding.finish()?;
+
+    let to_origin = |mut error: AssembleError| {
+        error.line = expansion.origin(error.line);
+        error
+    };
+    result
+        .map(|mut program| {
+            for line in program.lines.iter_mut() {
+                *line = expansion.origin(*line);
+            }
+            program.warnings = program.warnings.into_iter().map(to_origin).collect();
+            program
+        })
+        .map_err(|errors| errors.into_iter().map(to_origin).collect())
+}
+
+fn assemble_expanded<R: BufRead>(src: &mut R) -> Result<Program, Vec<AssembleError>> {
+    let mut parser = Parser::new(src).map_err(|e| vec![e])?;
+    let mut symbol_table = SymbolTable::new();
+    let mut instructions = Vec::new();
+    let mut lines = Vec::new();
+    let mut words = Vec::new();
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut constants = Vec::new();
+    let mut fixups = Vec::new();
+    let mut referenced_symbols = HashSet::new();
+    let mut unused_labels = HashMap::new();
+
+    while parser.has_more_commands() {
+        match parser.command_type() {
+            CommandType::Directive => match parser.directive().as_str() {
+                "equ" => match crate::read_constant(&parser) {
+                    Ok(constant) => constants.push(constant),
+                    Err(e) => errors.push(e),
+                },
+                _ => {
+                    let range = parser.directive_range();
+                    errors.push(parser.error(range, ErrorKind::UnknownDirective));
+                }
+            },
+            _ => match parser.instruction() {
+                Ok(instruction) => {
+                    lines.push(parser.line_number());
+                    if parser.command_type() != CommandType::Label && words.len() == ROM_SIZE {
+                        let range = parser.command_range();
+                        warnings.push(parser.error(range, ErrorKind::RomOverflow));
+                    }
+
+                    match &instruction {
+                        Instruction::Address { value, span } => {
+                            if let Value::Constant(n) = value {
+                                words.push(*n);
+                            } else {
+                                // The symbols may be the labels or the constants defined later.
+                                fixups.push(Fixup {
+                                    index: words.len(),
+                                    value: value.clone(),
+                                    location: parser.error_at(span, ErrorKind::InvalidSymbol),
+                                });
+                                words.push(0);
+                            }
+                        }
+                        Instruction::Compute {
+                            dest, comp, jump, ..
+                        } => words.push(code::encode(*dest, *comp, *jump)),
+                        Instruction::Label { name, span } => {
+                            let address = words.len() as u16;
+                            match crate::define_label(&mut symbol_table, name.clone(), address) {
+                                Ok(()) => {
+                                    let warning = parser.error_at(span, ErrorKind::UnusedLabel);
+                                    unused_labels.insert(name.clone(), warning);
+                                }
+                                Err(kind) => errors.push(parser.error_at(span, kind)),
+                            }
+                        }
+                    }
+                    instructions.push(instruction);
+                }
+                Err(e) => {
+                    // Keep the addresses of the following labels.
+                    if parser.command_type() != CommandType::Label {
+                        words.push(0);
+                    }
+                    errors.extend(e);
+                }
+            },
+        }
+
+        parser.advance().map_err(|e| vec![e])?;
+    }
+
+    let definitions = constants
+        .iter()
+        .map(|constant| (constant.name.clone(), constant.expr.clone()))
+        .collect();
+    crate::define_constants(
+        constants,
+        &mut symbol_table,
+        |s| {
+            referenced_symbols.insert(s.to_string());
+        },
+        &mut errors,
+    );
+
+    // Backpatch in the order of the references, which allocates the variables in the same order
+    // as the two pass assembly.
+    let mut var_address = VARIABLE_ADDRESS_BEGIN;
+    for mut fixup in fixups {
+        let symbol = match fixup.value {
+            Value::Symbol(symbol) => symbol,
+            Value::Expression(expr) => {
+                let result = expression::evaluate(&expr, |s| {
+                    referenced_symbols.insert(s.to_string());
+                    symbol_table.get_address(s)
+                });
+                match result {
+                    Ok(n) => words[fixup.index] = n,
+                    Err(kind) => {
+                        fixup.location.kind = kind;
+                        errors.push(fixup.location);
+                    }
+                }
+                continue;
+            }
+            Value::Constant(_) => unreachable!(),
+        };
+
+        if let Some(n) = symbol_table.get_address(&symbol) {
+            words[fixup.index] = n;
+            referenced_symbols.insert(symbol);
+        } else {
+            let (n, warning) =
+                crate::allocate_variable(&mut symbol_table, &mut var_address, symbol);
+            words[fixup.index] = n;
+            if let Some(kind) = warning {
+                fixup.location.kind = kind;
+                warnings.push(fixup.location);
+            }
+        }
+    }
+
+    warnings.sort_by_key(|warning| warning.line);
+    warnings.extend(crate::unused_label_warnings(
+        unused_labels,
+        &referenced_symbols,
+    ));
+
+    if errors.is_empty() {
+        Ok(Program {
+            instructions,
+            lines,
+            symbol_table,
+            words,
+            warnings,
+            constants: definitions,
+        })
+    } else {
+        errors.sort_by_key(|error| error.line);
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble_program;
+    use std::io::Cursor;
+
+    fn assemble_str(src: &str) -> Result<Program, Vec<AssembleError>> {
+        assemble_stream(Cursor::new(src))
+    }
+
+    #[test]
+    fn assemble_stream_test() {
+        let src = ".equ ROWS 4\n\
+                   .macro INC addr\n\
+                   \x20   @addr\n\
+                   \x20   M=M+1\n\
+                   .endm\n\
+                   \x20   @i\n\
+                   \x20   M=0\n\
+                   (LOOP)\n\
+                   \x20   INC i\n\
+                   \x20   @END-1\n\
+                   \x20   D=A\n\
+                   \x20   @ROWS\n\
+                   \x20   D=D-A\n\
+                   \x20   @LOOP\n\
+                   \x20   D;JLT\n\
+                   (END)\n\
+                   \x20   @END\n\
+                   \x20   0;JMP";
+        let stream = assemble_str(src).unwrap();
+        let program = assemble_program(src).unwrap();
+
+        assert_eq!(program.words, stream.words);
+        assert_eq!(program.lines, stream.lines);
+        assert_eq!(program.instructions, stream.instructions);
+        assert_eq!(Some(16), stream.symbol_table.get_address("i"));
+        assert_eq!(9, stream.words[4]);
+    }
+
+    #[test]
+    fn assemble_stream_warning_test() {
+        let src = "(UNUSED)\n@a\n@b\n@LOOP\n(LOOP)\n@c";
+        let stream = assemble_str(src).unwrap();
+        assert_eq!(vec![16, 17, 3, 18], stream.words);
+        assert_eq!(1, stream.warnings.len());
+        assert_eq!(1, stream.warnings[0].line);
+    }
+
+    #[test]
+    fn assemble_stream_error_test() {
+        let src = "@FOO+1\n\
+                   D=A+M\n\
+                   (LOOP)\n\
+                   (LOOP)\n\
+                   @1x";
+        let errors = assemble_str(src).unwrap_err();
+        let kinds: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.text.as_str(), e.kind.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                (1, "FOO+1", ErrorKind::UndefinedSymbol.to_string()),
+                (
+                    2,
+                    "A+M",
+                    ErrorKind::UnencodableComp("D+M".into()).to_string()
+                ),
+                (4, "LOOP", ErrorKind::DuplicateLabel.to_string()),
+                (5, "1x", ErrorKind::InvalidConstant.to_string()),
+            ],
+            kinds
+        );
+
+        let errors = assemble_str(".macro INC\n@1").unwrap_err();
+        assert!(matches!(errors[0].kind, ErrorKind::UnterminatedMacro));
+    }
+}
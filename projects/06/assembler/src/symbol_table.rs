@@ -1,59 +1,199 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::str::FromStr;
 
 type Symbol = String;
-type Address = u16;
+pub type Address = u16;
+
+/// The symbols defined by the Hack platform.
+pub const PREDEFINED_SYMBOLS: [(&str, Address); 23] = [
+    ("SP", 0),
+    ("LCL", 1),
+    ("ARG", 2),
+    ("THIS", 3),
+    ("THAT", 4),
+    ("R0", 0),
+    ("R1", 1),
+    ("R2", 2),
+    ("R3", 3),
+    ("R4", 4),
+    ("R5", 5),
+    ("R6", 6),
+    ("R7", 7),
+    ("R8", 8),
+    ("R9", 9),
+    ("R10", 10),
+    ("R11", 11),
+    ("R12", 12),
+    ("R13", 13),
+    ("R14", 14),
+    ("R15", 15),
+    ("SCREEN", 0x4000),
+    ("KBD", 0x6000),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymbolKind {
+    /// Defined by the Hack platform.
+    Predefined,
+    /// ROM address defined by `(LABEL)`.
+    Label,
+    /// Value defined by `.equ`.
+    Constant,
+    /// RAM address allocated automatically.
+    Variable,
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            SymbolKind::Predefined => "predefined",
+            SymbolKind::Label => "label",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Variable => "variable",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for SymbolKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "predefined" => Ok(SymbolKind::Predefined),
+            "label" => Ok(SymbolKind::Label),
+            "constant" => Ok(SymbolKind::Constant),
+            "variable" => Ok(SymbolKind::Variable),
+            _ => Err(()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct SymbolTable {
-    table: HashMap<Symbol, Address>,
+    table: HashMap<Symbol, (Address, SymbolKind)>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
-        let mut table: HashMap<Symbol, Address> = HashMap::with_capacity(32);
-
-        for (k, v) in [
-            ("SP", 0),
-            ("LCL", 1),
-            ("ARG", 2),
-            ("THIS", 3),
-            ("THAT", 4),
-            ("R0", 0),
-            ("R1", 1),
-            ("R2", 2),
-            ("R3", 3),
-            ("R4", 4),
-            ("R5", 5),
-            ("R6", 6),
-            ("R7", 7),
-            ("R8", 8),
-            ("R9", 9),
-            ("R10", 10),
-            ("R11", 11),
-            ("R12", 12),
-            ("R13", 13),
-            ("R14", 14),
-            ("R15", 15),
-            ("SCREEN", 0x4000),
-            ("KBD", 0x6000),
-        ]
-        .iter()
-        {
-            table.insert(k.to_string(), *v);
+        let mut table: HashMap<Symbol, (Address, SymbolKind)> = HashMap::with_capacity(32);
+
+        for (k, v) in PREDEFINED_SYMBOLS.iter() {
+            table.insert(k.to_string(), (*v, SymbolKind::Predefined));
         }
 
         Self { table }
     }
 
-    pub fn add_entry(&mut self, symbol: Symbol, address: Address) {
-        self.table.insert(symbol, address);
+    pub fn add_entry(&mut self, symbol: Symbol, address: Address, kind: SymbolKind) {
+        self.table.insert(symbol, (address, kind));
     }
 
     // pub fn contains(&self, symbol: &Symbol) -> bool {
     //     self.table.contains_key(symbol)
     // }
 
-    pub fn get_address(&self, symbol: &Symbol) -> Option<Address> {
-        self.table.get(symbol).cloned()
+    pub fn get_address(&self, symbol: &str) -> Option<Address> {
+        self.table.get(symbol).map(|(address, _)| *address)
+    }
+
+    pub fn get_kind(&self, symbol: &str) -> Option<SymbolKind> {
+        self.table.get(symbol).map(|(_, kind)| *kind)
+    }
+
+    /// Return all entries sorted by kind, address and name.
+    pub fn entries(&self) -> Vec<(&str, Address, SymbolKind)> {
+        let mut entries: Vec<_> = self
+            .table
+            .iter()
+            .map(|(symbol, (address, kind))| (symbol.as_str(), *address, *kind))
+            .collect();
+        entries.sort_by_key(|(symbol, address, kind)| (*kind, *address, *symbol));
+
+        entries
+    }
+
+    /// Write the entries in the tab separated format of .sym files.
+    pub fn write_sym<W: Write>(&self, dst: &mut W) -> io::Result<()> {
+        writeln!(dst, "name\tkind\taddress")?;
+        for (symbol, address, kind) in self.entries() {
+            writeln!(dst, "{}\t{}\t{}", symbol, kind, address)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the entries written by `write_sym`.
+    pub fn read_sym(src: &str) -> io::Result<Self> {
+        let mut table = HashMap::new();
+
+        for (i, line) in src.lines().enumerate().skip(1) {
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            let entry = match fields.as_slice() {
+                [symbol, kind, address] => kind
+                    .parse::<SymbolKind>()
+                    .ok()
+                    .zip(address.parse::<Address>().ok())
+                    .map(|(kind, address)| (symbol.to_string(), (address, kind))),
+                _ => None,
+            };
+
+            let (symbol, entry) = entry.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed symbol entry at line {}: {:?}", i + 1, line),
+                )
+            })?;
+            table.insert(symbol, entry);
+        }
+
+        Ok(Self { table })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sym_test() {
+        let mut table = SymbolTable::new();
+        table.add_entry("LOOP".to_string(), 4, SymbolKind::Label);
+        table.add_entry("i".to_string(), 16, SymbolKind::Variable);
+        table.add_entry("END".to_string(), 2, SymbolKind::Label);
+
+        let mut output = Vec::<u8>::new();
+        table.write_sym(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!("name\tkind\taddress", lines[0]);
+        assert_eq!("R0\tpredefined\t0", lines[1]);
+        assert_eq!("SP\tpredefined\t0", lines[2]);
+        assert_eq!(
+            vec!["END\tlabel\t2", "LOOP\tlabel\t4", "i\tvariable\t16"],
+            lines[24..].to_vec()
+        );
+
+        let table = SymbolTable::read_sym(&output).unwrap();
+        assert_eq!(Some(4), table.get_address("LOOP"));
+        assert_eq!(Some(SymbolKind::Variable), table.get_kind("i"));
+        assert_eq!(Some(SymbolKind::Predefined), table.get_kind("KBD"));
+
+        assert!(SymbolTable::read_sym("name\tkind\taddress\nLOOP\tlabel").is_err());
     }
 }
@@ -0,0 +1,301 @@
+use crate::error::EmulatorError;
+
+/// The number of words in ROM.
+pub const ROM_SIZE: usize = 0x8000;
+/// The base address of the screen memory map.
+pub const SCREEN: u16 = 0x4000;
+/// The address of the keyboard memory map.
+pub const KBD: u16 = 0x6000;
+/// The number of addressable words of RAM, screen and keyboard.
+pub const MEMORY_SIZE: usize = KBD as usize + 1;
+
+const C_INSTRUCTION: u16 = 0x8000;
+const A_BIT: u16 = 0x1000;
+const DEST_A: u16 = 0b100_000;
+const DEST_D: u16 = 0b010_000;
+const DEST_M: u16 = 0b001_000;
+const DEST_MASK: u16 = 0b111_000;
+const JUMP_MASK: u16 = 0b111;
+const JMP: u16 = 0b111;
+
+/// Whether the program is still running after a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    /// The program is in a loop which never changes the machine state, such as `@END; 0;JMP`.
+    Halted,
+}
+
+/// The memory accessed by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub address: u16,
+    pub is_read: bool,
+    /// The old and the new values if the memory is written.
+    pub write: Option<(u16, u16)>,
+}
+
+/// The Hack computer.
+#[derive(Debug, Clone)]
+pub struct Computer {
+    rom: Vec<u16>,
+    ram: Vec<u16>,
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+    /// The number of executed instructions.
+    pub cycles: u64,
+    /// The memory accessed by the last instruction.
+    last_access: Option<Access>,
+}
+
+impl Computer {
+    /// Create the computer with the program in ROM and the cleared RAM.
+    pub fn new(program: &[u16]) -> Result<Self, EmulatorError> {
+        if ROM_SIZE < program.len() {
+            return Err(EmulatorError::RomOverflow(program.len()));
+        }
+
+        let mut rom = vec![0; ROM_SIZE];
+        rom[..program.len()].copy_from_slice(program);
+        Ok(Computer {
+            rom,
+            ram: vec![0; MEMORY_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            cycles: 0,
+            last_access: None,
+        })
+    }
+
+    pub fn rom(&self) -> &[u16] {
+        &self.rom
+    }
+
+    /// The memory including the screen and the keyboard.
+    pub fn ram(&self) -> &[u16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u16] {
+        &mut self.ram
+    }
+
+    pub fn last_access(&self) -> Option<Access> {
+        self.last_access
+    }
+
+    /// Press the key of the code, or release it by 0.
+    pub fn set_key(&mut self, code: u16) {
+        self.ram[KBD as usize] = code;
+    }
+
+    /// Execute an instruction.
+    pub fn step(&mut self) -> Result<State, EmulatorError> {
+        let pc = self.pc;
+        let instruction = self.rom[pc as usize];
+        self.last_access = None;
+        if instruction & C_INSTRUCTION == 0 {
+            self.a = instruction;
+            self.pc = next_address(pc);
+            self.cycles += 1;
+            return Ok(State::Running);
+        }
+
+        // M and the jump target are addressed by A before this instruction updates it.
+        let address = self.a;
+        let is_read = instruction & A_BIT != 0;
+        let y = if is_read {
+            self.load(pc, address)?
+        } else {
+            self.a
+        };
+        let out = alu(self.d, y, instruction >> 6);
+
+        let write = if instruction & DEST_M != 0 {
+            Some((self.store(pc, address, out)?, out))
+        } else {
+            None
+        };
+        if is_read || write.is_some() {
+            self.last_access = Some(Access {
+                address,
+                is_read,
+                write,
+            });
+        }
+        if instruction & DEST_A != 0 {
+            self.a = out;
+        }
+        if instruction & DEST_D != 0 {
+            self.d = out;
+        }
+
+        let out = out as i16;
+        let jump = instruction & JUMP_MASK;
+        let is_taken = (jump & 0b100 != 0 && out < 0)
+            || (jump & 0b010 != 0 && out == 0)
+            || (jump & 0b001 != 0 && out > 0);
+        self.pc = if is_taken {
+            address & (ROM_SIZE as u16 - 1)
+        } else {
+            next_address(pc)
+        };
+        self.cycles += 1;
+
+        if is_taken && self.is_self_loop(self.pc, pc) {
+            Ok(State::Halted)
+        } else {
+            Ok(State::Running)
+        }
+    }
+
+    /// Execute the instructions until the program halts or the cycles elapse.
+    pub fn run(&mut self, cycles: u64) -> Result<State, EmulatorError> {
+        for _ in 0..cycles {
+            if self.step()? == State::Halted {
+                return Ok(State::Halted);
+            }
+        }
+
+        Ok(State::Running)
+    }
+
+    /// Execute the instructions until the program halts. It never returns for an endless program.
+    pub fn run_until_halt(&mut self) -> Result<(), EmulatorError> {
+        while self.step()? == State::Running {}
+        Ok(())
+    }
+
+    fn load(&self, pc: u16, address: u16) -> Result<u16, EmulatorError> {
+        self.ram
+            .get(address as usize)
+            .copied()
+            .ok_or(EmulatorError::InvalidAddress { pc, address })
+    }
+
+    /// Store the value and return the old one.
+    fn store(&mut self, pc: u16, address: u16, value: u16) -> Result<u16, EmulatorError> {
+        match self.ram.get_mut(address as usize) {
+            // The keyboard is read-only.
+            Some(word) if address == KBD => Ok(*word),
+            Some(word) => Ok(std::mem::replace(word, value)),
+            None => Err(EmulatorError::InvalidAddress { pc, address }),
+        }
+    }
+
+    /// Check the jump from `end` back to `begin` repeats the same instructions forever.
+    ///
+    /// The loop stores nothing and has no other jumps. Besides, the last jump is unconditional or
+    /// the loop reads no memory, since the keyboard may change the condition.
+    fn is_self_loop(&self, begin: u16, end: u16) -> bool {
+        if end < begin {
+            return false;
+        }
+
+        let body = &self.rom[begin as usize..=end as usize];
+        let (last, rest) = body.split_last().unwrap();
+        let is_c = |word: &u16| word & C_INSTRUCTION != 0;
+        let stores = body.iter().any(|w| is_c(w) && w & DEST_MASK != 0);
+        let jumps = rest.iter().any(|w| is_c(w) && w & JUMP_MASK != 0);
+        let reads = body.iter().any(|w| is_c(w) && w & A_BIT != 0);
+
+        !stores && !jumps && (last & JUMP_MASK == JMP || !reads)
+    }
+}
+
+fn next_address(pc: u16) -> u16 {
+    (pc + 1) & (ROM_SIZE as u16 - 1)
+}
+
+/// Compute by the control bits, zx, nx, zy, ny, f and no from the most significant.
+fn alu(x: u16, y: u16, control: u16) -> u16 {
+    let bit = |n: u16| control & (1 << n) != 0;
+    let x = if bit(5) { 0 } else { x };
+    let x = if bit(4) { !x } else { x };
+    let y = if bit(3) { 0 } else { y };
+    let y = if bit(2) { !y } else { y };
+    let out = if bit(1) { x.wrapping_add(y) } else { x & y };
+    if bit(0) {
+        !out
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alu_test() {
+        // 0, 1, -1, D+A, D-A, A-D, D&A and D|A.
+        assert_eq!(0, alu(5, 3, 0b101010));
+        assert_eq!(1, alu(5, 3, 0b111111));
+        assert_eq!(0xFFFF, alu(5, 3, 0b111010));
+        assert_eq!(8, alu(5, 3, 0b000010));
+        assert_eq!(2, alu(5, 3, 0b010011));
+        assert_eq!(0xFFFE, alu(5, 3, 0b000111));
+        assert_eq!(1, alu(5, 3, 0b000000));
+        assert_eq!(7, alu(5, 3, 0b010101));
+    }
+
+    #[test]
+    fn step_test() {
+        // @5; D=A; @3; D=D-A; @100; M=D; AM=M+1; AD=D-1;JGT
+        let program = [5, 0xEC10, 3, 0xE4D0, 100, 0xE308, 0xFDE8, 0xE3B1];
+        let mut computer = Computer::new(&program).unwrap();
+        assert_eq!(State::Running, computer.run(7).unwrap());
+        assert_eq!(3, computer.ram()[100]);
+        assert_eq!((3, 2, 7), (computer.a, computer.d, computer.pc));
+
+        assert_eq!(
+            Some(Access {
+                address: 100,
+                is_read: true,
+                write: Some((2, 3))
+            }),
+            computer.last_access()
+        );
+
+        // The jump goes to the address in A before the instruction.
+        computer.step().unwrap();
+        assert_eq!(None, computer.last_access());
+        assert_eq!((1, 1, 3), (computer.a, computer.d, computer.pc));
+        assert_eq!(8, computer.cycles);
+    }
+
+    #[test]
+    fn halt_test() {
+        // @0x6000; D=M; @0; D;JEQ (END) @4; 0;JMP
+        let program = [0x6000, 0xFC10, 0, 0xE302, 4, 0xEA87];
+        let mut computer = Computer::new(&program).unwrap();
+
+        // Waiting for a key is not a halt.
+        assert_eq!(State::Running, computer.run(100).unwrap());
+        computer.set_key(65);
+        computer.run_until_halt().unwrap();
+        assert_eq!(4, computer.pc);
+        assert_eq!(State::Running, computer.step().unwrap());
+        assert_eq!(State::Halted, computer.step().unwrap());
+    }
+
+    #[test]
+    fn error_test() {
+        // @0x7000; M=1
+        let mut computer = Computer::new(&[0x7000, 0xEFC8]).unwrap();
+        computer.step().unwrap();
+        assert!(matches!(
+            computer.step(),
+            Err(EmulatorError::InvalidAddress {
+                pc: 1,
+                address: 0x7000
+            })
+        ));
+        assert!(matches!(
+            Computer::new(&vec![0; ROM_SIZE + 1]),
+            Err(EmulatorError::RomOverflow(32769))
+        ));
+    }
+}
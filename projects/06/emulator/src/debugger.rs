@@ -0,0 +1,607 @@
+use crate::computer::{Access, Computer, State, KBD, ROM_SIZE};
+use crate::disassembly::{self, Symbols};
+use crate::error::EmulatorError;
+use crate::snapshot;
+use assembler::symbol_table::SymbolKind;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::io::prelude::*;
+use std::ops::Range;
+
+/// The bottom of the stack of the VM.
+const STACK_BASE: u16 = 256;
+/// The instructions listed before and after PC.
+const LIST_CONTEXT: u16 = 4;
+/// The address of the stack pointer.
+const SP: u16 = 0;
+/// The instructions which can be undone, about 50MB of the history.
+const HISTORY_LIMIT: usize = 1 << 22;
+
+const HELP: &str = "\
+break ADDR|LABEL      stop before the instruction (b)
+delete ADDR|LABEL     remove the breakpoint
+watch ADDR|SYMBOL     stop after the memory is written
+rwatch ADDR|SYMBOL    stop after the memory is read
+awatch ADDR|SYMBOL    stop after the memory is read or written
+unwatch ADDR|SYMBOL   remove the watchpoint
+step [N]              execute N instructions (s)
+continue              run until a breakpoint, a watchpoint or halt (c)
+reverse-step [N]      undo N instructions (rs)
+reverse-continue      undo until a breakpoint, a write to a watched address or the start of
+                      the history, ignoring the rwatch watchpoints (rc)
+print START[..END]    show the memory, like `print 256..260` or `print LCL` (p)
+stack                 show the stack from 256 to SP
+registers             show A, D, PC and the cycles (r)
+list                  disassemble around PC (l)
+info                  show the breakpoints and the watchpoints
+save PATH             save the machine state into the snapshot file
+restore PATH          restore the machine state from the snapshot file
+quit                  exit (q)
+An empty line repeats the last command.";
+
+/// The memory accesses stopping the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    Access,
+}
+
+impl Watch {
+    pub(crate) fn is_triggered(self, access: &Access) -> bool {
+        match self {
+            Watch::Read => access.is_read,
+            Watch::Write => access.write.is_some(),
+            Watch::Access => true,
+        }
+    }
+}
+
+/// A command of the debugger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Break(u16),
+    Delete(u16),
+    Watch(u16, Watch),
+    Unwatch(u16),
+    Step(u64),
+    Continue,
+    ReverseStep(u64),
+    ReverseContinue,
+    Print(Range<u16>),
+    Stack,
+    Registers,
+    List,
+    Info,
+    Save(String),
+    Restore(String),
+    Help,
+    Quit,
+}
+
+/// The reason the execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Stepped,
+    Breakpoint,
+    Watchpoint(Access),
+    Halted,
+    /// No more history to undo.
+    Start,
+}
+
+/// The state before an instruction, to undo it.
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    a: u16,
+    d: u16,
+    pc: u16,
+    /// The address and the old value of the written memory.
+    write: Option<(u16, u16)>,
+}
+
+/// The debugger driving the computer by commands.
+pub struct Debugger<'a> {
+    computer: &'a mut Computer,
+    symbols: &'a Symbols,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: BTreeMap<u16, Watch>,
+    history: VecDeque<Undo>,
+    last_command: String,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(computer: &'a mut Computer, symbols: &'a Symbols) -> Self {
+        Debugger {
+            computer,
+            symbols,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            history: VecDeque::new(),
+            last_command: String::new(),
+        }
+    }
+
+    /// Read the commands until `quit` or the end of the input.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(out, "(hack) ")?;
+            out.flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return writeln!(out),
+            };
+            if !self.execute(&line, out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Execute the command line and return false to quit.
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        if line.is_empty() {
+            return Ok(true);
+        }
+        self.last_command = line.clone();
+
+        let command = match parse_command(&line, self.symbols) {
+            Ok(command) => command,
+            Err(message) => {
+                writeln!(out, "error: {}", message)?;
+                return Ok(true);
+            }
+        };
+
+        match command {
+            Command::Break(address) => {
+                self.breakpoints.insert(address);
+                writeln!(out, "breakpoint at {}", self.rom_name(address))?;
+            }
+            Command::Delete(address) => {
+                if !self.breakpoints.remove(&address) {
+                    writeln!(out, "error: no breakpoint at {}", self.rom_name(address))?;
+                }
+            }
+            Command::Watch(address, watch) => {
+                self.watchpoints.insert(address, watch);
+                writeln!(out, "watchpoint on {}", self.ram_name(address))?;
+            }
+            Command::Unwatch(address) => {
+                if self.watchpoints.remove(&address).is_none() {
+                    writeln!(out, "error: no watchpoint on {}", self.ram_name(address))?;
+                }
+            }
+            Command::Step(n) => {
+                let stop = self.execute_instructions(n);
+                self.report(stop, out)?
+            }
+            Command::Continue => {
+                let stop = self.execute_instructions(u64::MAX);
+                self.report(stop, out)?
+            }
+            Command::ReverseStep(n) => {
+                let stop = self.undo_instructions(n);
+                self.report(Ok(stop), out)?
+            }
+            Command::ReverseContinue => {
+                let stop = self.undo_instructions(u64::MAX);
+                self.report(Ok(stop), out)?
+            }
+            Command::Print(range) => {
+                for address in range {
+                    writeln!(out, "{}", self.memory_line(address))?;
+                }
+            }
+            Command::Stack => self.print_stack(out)?,
+            Command::Registers => writeln!(
+                out,
+                "A: {}, D: {}, PC: {}, cycles: {}",
+                self.computer.a as i16,
+                self.computer.d as i16,
+                self.computer.pc,
+                self.computer.cycles
+            )?,
+            Command::List => {
+                let rom = self.computer.rom();
+                let pc = self.computer.pc;
+                let begin = pc.saturating_sub(LIST_CONTEXT);
+                let end = (pc + LIST_CONTEXT).min(rom.len() as u16 - 1);
+                for address in begin..=end {
+                    let marker = if address == pc { '>' } else { ' ' };
+                    let line = disassembly::listing(rom, address, self.symbols);
+                    writeln!(out, "{} {}", marker, line)?;
+                }
+            }
+            Command::Info => {
+                for address in &self.breakpoints {
+                    writeln!(out, "breakpoint at {}", self.rom_name(*address))?;
+                }
+                for (address, watch) in &self.watchpoints {
+                    let kind = match watch {
+                        Watch::Read => "read",
+                        Watch::Write => "write",
+                        Watch::Access => "access",
+                    };
+                    writeln!(out, "{} watchpoint on {}", kind, self.ram_name(*address))?;
+                }
+            }
+            Command::Save(path) => match snapshot::save(&path, self.computer) {
+                Ok(()) => writeln!(out, "saved the state at {} cycles", self.computer.cycles)?,
+                Err(error) => writeln!(out, "error: {}", error)?,
+            },
+            Command::Restore(path) => match snapshot::load(&path) {
+                Ok(computer) => {
+                    *self.computer = computer;
+                    self.history.clear();
+                    writeln!(out, "restored the state at {} cycles", self.computer.cycles)?;
+                }
+                Err(error) => writeln!(out, "error: {}", error)?,
+            },
+            Command::Help => writeln!(out, "{}", HELP)?,
+            Command::Quit => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Report where the execution stopped.
+    fn report<W: Write>(&self, stop: Result<Stop, EmulatorError>, out: &mut W) -> io::Result<()> {
+        match stop {
+            Ok(Stop::Stepped) => {}
+            Ok(Stop::Breakpoint) => {
+                writeln!(out, "breakpoint at {}", self.rom_name(self.computer.pc))?
+            }
+            Ok(Stop::Watchpoint(access)) => {
+                let name = self.ram_name(access.address);
+                match access.write {
+                    Some((old, new)) => {
+                        let kind = if access.is_read {
+                            "read and written"
+                        } else {
+                            "written"
+                        };
+                        writeln!(out, "{} {}: {} -> {}", name, kind, old as i16, new as i16)?
+                    }
+                    None => {
+                        let value = self.computer.ram()[access.address as usize];
+                        writeln!(out, "{} read: {}", name, value as i16)?
+                    }
+                }
+            }
+            Ok(Stop::Halted) => writeln!(out, "halted after {} cycles", self.computer.cycles)?,
+            Ok(Stop::Start) => writeln!(
+                out,
+                "reached the start of the history at {} cycles",
+                self.computer.cycles
+            )?,
+            Err(error) => writeln!(out, "error: {}", error)?,
+        }
+
+        let pc = self.computer.pc;
+        let line = disassembly::listing(self.computer.rom(), pc, self.symbols);
+        writeln!(out, "> {}", line)
+    }
+
+    fn execute_instructions(&mut self, n: u64) -> Result<Stop, EmulatorError> {
+        for _ in 0..n {
+            let (a, d, pc) = (self.computer.a, self.computer.d, self.computer.pc);
+            let state = self.computer.step()?;
+            let access = self.computer.last_access();
+            if self.history.len() == HISTORY_LIMIT {
+                self.history.pop_front();
+            }
+            self.history.push_back(Undo {
+                a,
+                d,
+                pc,
+                write: access.and_then(|access| Some((access.address, access.write?.0))),
+            });
+
+            if let Some(access) = access {
+                let watch = self.watchpoints.get(&access.address);
+                if watch.is_some_and(|watch| watch.is_triggered(&access)) {
+                    return Ok(Stop::Watchpoint(access));
+                }
+            }
+            if state == State::Halted {
+                return Ok(Stop::Halted);
+            }
+            if self.breakpoints.contains(&self.computer.pc) {
+                return Ok(Stop::Breakpoint);
+            }
+        }
+
+        Ok(Stop::Stepped)
+    }
+
+    /// Undo up to n instructions. It stops after undoing a write to an address watched by `watch`
+    /// or `awatch`, with PC at the writing instruction and the old value in the memory.
+    fn undo_instructions(&mut self, n: u64) -> Stop {
+        for _ in 0..n {
+            let undo = match self.history.pop_back() {
+                Some(undo) => undo,
+                None => return Stop::Start,
+            };
+            let written = undo.write.map(|(address, old)| {
+                let new = std::mem::replace(&mut self.computer.ram_mut()[address as usize], old);
+                Access {
+                    address,
+                    is_read: false,
+                    write: Some((old, new)),
+                }
+            });
+            self.computer.a = undo.a;
+            self.computer.d = undo.d;
+            self.computer.pc = undo.pc;
+            self.computer.cycles -= 1;
+
+            if let Some(access) = written {
+                let watch = self.watchpoints.get(&access.address);
+                if watch.is_some_and(|watch| watch.is_triggered(&access)) {
+                    return Stop::Watchpoint(access);
+                }
+            }
+            if self.breakpoints.contains(&self.computer.pc) {
+                return Stop::Breakpoint;
+            }
+        }
+
+        Stop::Stepped
+    }
+
+    /// Show the stack from its base to below SP.
+    fn print_stack<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let sp = self.computer.ram()[SP as usize];
+        if !(STACK_BASE..=KBD).contains(&sp) {
+            return writeln!(out, "error: SP {} is out of the stack", sp as i16);
+        }
+        if sp == STACK_BASE {
+            return writeln!(out, "the stack is empty");
+        }
+        for address in STACK_BASE..sp {
+            writeln!(out, "{}", self.memory_line(address))?;
+        }
+
+        Ok(())
+    }
+
+    fn memory_line(&self, address: u16) -> String {
+        let value = self.computer.ram()[address as usize];
+        format!("{}: {}", self.ram_name(address), value as i16)
+    }
+
+    fn rom_name(&self, address: u16) -> String {
+        match self.symbols.label_at(address) {
+            Some(label) => format!("{} ({})", address, label),
+            None => address.to_string(),
+        }
+    }
+
+    fn ram_name(&self, address: u16) -> String {
+        match self.symbols.variable_at(address) {
+            Some(name) => format!("RAM[{}] ({})", address, name),
+            None => format!("RAM[{}]", address),
+        }
+    }
+}
+
+/// Parse the command line. The addresses are numbers or symbols.
+pub fn parse_command(line: &str, symbols: &Symbols) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let arg = words.next();
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected `{}`", extra));
+    }
+    let required = || arg.ok_or_else(|| format!("`{}` needs an argument", name));
+    let steps = || match arg {
+        Some(n) => n
+            .parse()
+            .map_err(|_| format!("`{}` is not a number of steps", n)),
+        None => Ok(1),
+    };
+    let no_arg = |command| match arg {
+        Some(arg) => Err(format!("unexpected `{}`", arg)),
+        None => Ok(command),
+    };
+
+    match name {
+        "b" | "break" => Ok(Command::Break(rom_address(required()?, symbols)?)),
+        "delete" => Ok(Command::Delete(rom_address(required()?, symbols)?)),
+        "watch" => Ok(Command::Watch(
+            ram_address(required()?, symbols)?,
+            Watch::Write,
+        )),
+        "rwatch" => Ok(Command::Watch(
+            ram_address(required()?, symbols)?,
+            Watch::Read,
+        )),
+        "awatch" => Ok(Command::Watch(
+            ram_address(required()?, symbols)?,
+            Watch::Access,
+        )),
+        "unwatch" => Ok(Command::Unwatch(ram_address(required()?, symbols)?)),
+        "s" | "step" => steps().map(Command::Step),
+        "p" | "print" => {
+            let arg = required()?;
+            match arg.split_once("..") {
+                Some((start, end)) => {
+                    let start = ram_address(start, symbols)?;
+                    let end = ram_address(end, symbols)?;
+                    if end < start {
+                        return Err(format!("`{}` is an empty range", arg));
+                    }
+                    Ok(Command::Print(start..end))
+                }
+                None => {
+                    let address = ram_address(arg, symbols)?;
+                    Ok(Command::Print(address..address + 1))
+                }
+            }
+        }
+        "save" => Ok(Command::Save(required()?.to_string())),
+        "restore" => Ok(Command::Restore(required()?.to_string())),
+        "c" | "continue" => no_arg(Command::Continue),
+        "rs" | "reverse-step" => steps().map(Command::ReverseStep),
+        "rc" | "reverse-continue" => no_arg(Command::ReverseContinue),
+        "stack" => no_arg(Command::Stack),
+        "r" | "registers" => no_arg(Command::Registers),
+        "l" | "list" => no_arg(Command::List),
+        "info" => no_arg(Command::Info),
+        "h" | "help" => no_arg(Command::Help),
+        "q" | "quit" => no_arg(Command::Quit),
+        _ => Err(format!("unknown command `{}`, try `help`", name)),
+    }
+}
+
+/// Parse a decimal or `0x` hexadecimal number.
+fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Resolve a ROM address or a label.
+fn rom_address(s: &str, symbols: &Symbols) -> Result<u16, String> {
+    let address = match (parse_number(s), symbols.get(s)) {
+        (Some(address), _) | (None, Some((address, SymbolKind::Label))) => address,
+        (None, Some(_)) => return Err(format!("`{}` is not a label", s)),
+        (None, None) => return Err(format!("unknown label `{}`", s)),
+    };
+    if ROM_SIZE <= address as usize {
+        return Err(format!("{} is out of ROM", address));
+    }
+
+    Ok(address)
+}
+
+/// Resolve a RAM address or a symbol other than labels, like SP.
+fn ram_address(s: &str, symbols: &Symbols) -> Result<u16, String> {
+    let address = match (parse_number(s), symbols.get(s)) {
+        (Some(address), _) => address,
+        (None, Some((_, SymbolKind::Label))) => return Err(format!("`{}` is a label", s)),
+        (None, Some((address, _))) => address,
+        (None, None) => return Err(format!("unknown symbol `{}`", s)),
+    };
+    if KBD < address {
+        return Err(format!("{} is out of RAM", address));
+    }
+
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembly::tests::{symbols, PROGRAM};
+
+    /// Execute the commands and return the output.
+    fn debug(computer: &mut Computer, commands: &str) -> String {
+        let symbols = symbols();
+        let mut debugger = Debugger::new(computer, &symbols);
+        let mut out = Vec::new();
+        for line in commands.lines() {
+            debugger.execute(line, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parse_command_test() {
+        let symbols = symbols();
+        let parse = |line| parse_command(line, &symbols);
+        assert_eq!(Ok(Command::Break(2)), parse("break LOOP"));
+        assert_eq!(Ok(Command::Break(0x10)), parse("b 0x10"));
+        assert_eq!(Ok(Command::Watch(1, Watch::Write)), parse("watch LCL"));
+        assert_eq!(Ok(Command::Watch(16, Watch::Read)), parse("rwatch i"));
+        assert_eq!(Ok(Command::Step(1)), parse("s"));
+        assert_eq!(Ok(Command::Step(20)), parse("step 20"));
+        assert_eq!(Ok(Command::ReverseStep(1)), parse("rs"));
+        assert_eq!(Ok(Command::ReverseContinue), parse("reverse-continue"));
+        assert_eq!(Ok(Command::Print(256..260)), parse("print 256..260"));
+        assert_eq!(Ok(Command::Print(0x6000..0x6001)), parse("p KBD"));
+        assert_eq!(
+            Ok(Command::Save("a.snap".to_string())),
+            parse("save a.snap")
+        );
+
+        assert!(parse("break i").is_err());
+        assert!(parse("watch LOOP").is_err());
+        assert!(parse("break END").is_err());
+        assert!(parse("print 0x6001").is_err());
+        assert!(parse("print 3..2").is_err());
+        assert!(parse("step x").is_err());
+        assert!(parse("continue 3").is_err());
+        assert!(parse("jump 3").is_err());
+    }
+
+    #[test]
+    fn breakpoint_test() {
+        let mut computer = Computer::new(&PROGRAM).unwrap();
+        let out = debug(&mut computer, "break LOOP\nc\nregisters\nc");
+        assert_eq!(
+            "breakpoint at 2 (LOOP)\n\
+             breakpoint at 2 (LOOP)\n\
+             >     2  (LOOP)                   @LOOP\n\
+             A: 16, D: 0, PC: 2, cycles: 2\n\
+             halted after 4 cycles\n\
+             >     2  (LOOP)                   @LOOP\n",
+            out
+        );
+    }
+
+    #[test]
+    fn watchpoint_test() {
+        // @i; M=M+1; D=M; @SP; M=D
+        let program = [16, 0xFDC8, 0xFC10, 0, 0xE308];
+        let mut computer = Computer::new(&program).unwrap();
+        let out = debug(&mut computer, "watch i\nwatch SP\nc\nc\n");
+        assert!(out.contains("RAM[16] (i) read and written: 0 -> 1\n>     2"));
+        assert!(out.contains("RAM[0] (SP) written: 0 -> 1\n"));
+
+        let mut computer = Computer::new(&program).unwrap();
+        let out = debug(&mut computer, "rwatch i\nc\n\nunwatch i\ninfo");
+        // M=M+1 and D=M read it, and then an empty line repeats `c`.
+        assert!(out.contains("RAM[16] (i) read and written: 0 -> 1\n>     2"));
+        assert!(out.contains("RAM[16] (i) read: 1\n>     3"));
+        assert!(!out.contains("read watchpoint"));
+    }
+
+    #[test]
+    fn reverse_test() {
+        // @i; M=M+1; D=M; @LCL; M=D; @i; M=M+1
+        let program = [16, 0xFDC8, 0xFC10, 1, 0xE308, 16, 0xFDC8];
+        let mut computer = Computer::new(&program).unwrap();
+        let out = debug(
+            &mut computer,
+            "step 7\nwatch LCL\nrc\nregisters\nrs 100\nregisters\nprint i",
+        );
+        assert!(out.contains("RAM[1] (LCL) written: 0 -> 1\n>     4"));
+        assert!(out.contains("A: 1, D: 1, PC: 4, cycles: 4\n"));
+        assert!(out.contains("reached the start of the history at 0 cycles\n"));
+        assert!(out.ends_with("A: 0, D: 0, PC: 0, cycles: 0\nRAM[16] (i): 0\n"));
+        assert_eq!((0, 0), (computer.ram()[1], computer.ram()[16]));
+    }
+
+    #[test]
+    fn memory_test() {
+        let mut computer = Computer::new(&[0]).unwrap();
+        computer.ram_mut()[0] = 258;
+        computer.ram_mut()[256] = 7;
+        computer.ram_mut()[257] = -1i16 as u16;
+        assert_eq!("RAM[256]: 7\nRAM[257]: -1\n", debug(&mut computer, "stack"));
+        assert_eq!(
+            "RAM[0] (SP): 258\nRAM[1] (LCL): 0\n",
+            debug(&mut computer, "print SP..2")
+        );
+
+        computer.ram_mut()[0] = 256;
+        assert_eq!("the stack is empty\n", debug(&mut computer, "stack"));
+    }
+}
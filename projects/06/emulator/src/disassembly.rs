@@ -0,0 +1,139 @@
+use crate::error::EmulatorError;
+use assembler::disassembler::{self, Usage};
+use assembler::symbol_table::{SymbolKind, SymbolTable, PREDEFINED_SYMBOLS};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The names of the addresses from a .sym file.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    /// The labels by ROM address, the first in name order if several share an address.
+    labels: HashMap<u16, String>,
+    /// The variables and the predefined symbols by RAM address.
+    variables: HashMap<u16, String>,
+    addresses: HashMap<String, (u16, SymbolKind)>,
+}
+
+impl Symbols {
+    pub fn new(table: &SymbolTable) -> Self {
+        let mut symbols = Symbols::default();
+        // The predefined symbols are prior to the others in the order of the table, like SP to R0.
+        for (name, address) in PREDEFINED_SYMBOLS.iter() {
+            symbols
+                .variables
+                .entry(*address)
+                .or_insert_with(|| name.to_string());
+        }
+
+        for (name, address, kind) in table.entries() {
+            symbols.addresses.insert(name.to_string(), (address, kind));
+            let names = match kind {
+                SymbolKind::Label => &mut symbols.labels,
+                SymbolKind::Variable => &mut symbols.variables,
+                _ => continue,
+            };
+            names.entry(address).or_insert_with(|| name.to_string());
+        }
+
+        symbols
+    }
+
+    /// Read the .sym file written by the assembler.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EmulatorError> {
+        let table = SymbolTable::read_sym(&fs::read_to_string(path)?)?;
+        Ok(Symbols::new(&table))
+    }
+
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// The labels and their ROM addresses in no particular order.
+    pub fn labels(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels
+            .iter()
+            .map(|(address, label)| (*address, label.as_str()))
+    }
+
+    pub fn variable_at(&self, address: u16) -> Option<&str> {
+        self.variables.get(&address).map(String::as_str)
+    }
+
+    /// The address and the kind of the symbol, including the predefined ones.
+    pub fn get(&self, name: &str) -> Option<(u16, SymbolKind)> {
+        let predefined = || {
+            PREDEFINED_SYMBOLS
+                .iter()
+                .find(|(symbol, _)| *symbol == name)
+                .map(|(_, address)| (*address, SymbolKind::Predefined))
+        };
+        self.addresses.get(name).copied().or_else(predefined)
+    }
+}
+
+/// Decode the word into the assembly. The next word tells what the value in A is used for.
+pub fn disassemble(word: u16, next: Option<u16>, symbols: &Symbols) -> String {
+    disassembler::decode(word, next, |value, usage| operand(value, usage, symbols))
+        .unwrap_or_else(|| format!("??? {:016b}", word))
+}
+
+/// The line of the instruction in a listing, the address, the label and the assembly.
+pub fn listing(rom: &[u16], address: u16, symbols: &Symbols) -> String {
+    let next = rom.get(address as usize + 1).copied();
+    let instruction = disassemble(rom[address as usize], next, symbols);
+    let label = symbols
+        .label_at(address)
+        .map_or_else(String::new, |label| format!("({})", label));
+    format!("{:5}  {:<24} {}", address, label, instruction)
+}
+
+/// Name the value by a label if it is a jump target, or by a variable if it addresses memory.
+fn operand(value: u16, usage: Usage, symbols: &Symbols) -> Option<String> {
+    let name = if usage.jumps {
+        symbols.label_at(value)
+    } else if usage.accesses_memory {
+        symbols.variable_at(value)
+    } else {
+        None
+    };
+    name.map(str::to_string)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// `@i; M=M+1; (LOOP) @LOOP; 0;JMP`, the program of `symbols`.
+    pub(crate) const PROGRAM: [u16; 4] = [16, 0xFDC8, 2, 0xEA87];
+
+    pub(crate) fn symbols() -> Symbols {
+        let sym = "name\tkind\taddress\n\
+                   LOOP\tlabel\t2\n\
+                   i\tvariable\t16\n";
+        Symbols::new(&SymbolTable::read_sym(sym).unwrap())
+    }
+
+    #[test]
+    fn disassemble_test() {
+        let symbols = symbols();
+        assert_eq!("@LOOP", disassemble(2, Some(0xEA87), &symbols));
+        assert_eq!("@2", disassemble(2, Some(0xEC10), &symbols));
+        assert_eq!("@i", disassemble(16, Some(0xFC10), &symbols));
+        assert_eq!("@SP", disassemble(0, Some(0xFDC8), &symbols));
+        assert_eq!("@0", disassemble(0, None, &symbols));
+        assert_eq!("AM=M+1", disassemble(0xFDE8, None, &symbols));
+        assert_eq!("D;JGT", disassemble(0xE301, None, &symbols));
+        assert_eq!("??? 1000000000000000", disassemble(0x8000, None, &symbols));
+    }
+
+    #[test]
+    fn symbols_test() {
+        let symbols = symbols();
+        assert_eq!(Some("LOOP"), symbols.label_at(2));
+        assert_eq!(Some("R15"), symbols.variable_at(15));
+        assert_eq!(Some((16, SymbolKind::Variable)), symbols.get("i"));
+        assert_eq!(Some((0x6000, SymbolKind::Predefined)), symbols.get("KBD"));
+        assert_eq!(None, symbols.get("j"));
+    }
+}
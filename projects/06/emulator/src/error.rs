@@ -0,0 +1,66 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum EmulatorError {
+    Io(io::Error),
+    /// The line of a .hack file is not 16 binary digits. It holds the 1-origin line number.
+    InvalidWord(usize),
+    /// The program does not fit in ROM. It holds the number of words.
+    RomOverflow(usize),
+    /// The instruction at `pc` accessed memory out of RAM, screen and keyboard.
+    InvalidAddress {
+        pc: u16,
+        address: u16,
+    },
+    /// The rendered diagnostics of the assembly program loaded by a script.
+    Assemble(String),
+    /// The test script is malformed at the 1-origin line.
+    InvalidScript {
+        line: usize,
+        message: String,
+    },
+    /// The keyboard schedule is malformed at the 1-origin line.
+    InvalidSchedule {
+        line: usize,
+        message: String,
+    },
+    /// The initial RAM contents are malformed at the 1-origin line.
+    InvalidRam {
+        line: usize,
+        message: String,
+    },
+    /// The machine state file is malformed.
+    InvalidSnapshot(String),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use EmulatorError::*;
+        match self {
+            Io(e) => write!(f, "cannot read the program: {}", e),
+            InvalidWord(line) => write!(f, "line {} is not a 16-bit binary word", line),
+            RomOverflow(len) => write!(f, "program has {} words but ROM has 32768", len),
+            InvalidAddress { pc, address } => write!(
+                f,
+                "instruction at ROM[{}] accessed invalid address {:#06x}",
+                pc, address
+            ),
+            Assemble(diagnostics) => write!(f, "cannot assemble the program\n\n{}", diagnostics),
+            InvalidScript { line, message } => write!(f, "line {}: {}", line, message),
+            InvalidSchedule { line, message } => {
+                write!(f, "keyboard schedule line {}: {}", line, message)
+            }
+            InvalidRam { line, message } => write!(f, "RAM contents line {}: {}", line, message),
+            InvalidSnapshot(message) => write!(f, "invalid snapshot: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+impl From<io::Error> for EmulatorError {
+    fn from(e: io::Error) -> Self {
+        EmulatorError::Io(e)
+    }
+}
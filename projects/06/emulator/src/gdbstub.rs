@@ -0,0 +1,609 @@
+use crate::computer::{Computer, State, MEMORY_SIZE, ROM_SIZE};
+use crate::debugger::Watch;
+use crate::disassembly::Symbols;
+use assembler::symbol_table::SymbolKind;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+
+/// The byte address of RAM. ROM is from 0, and each word takes 2 bytes in little endian.
+const RAM_BASE: u32 = 0x10000;
+/// The instructions executed between the checks for the interrupt from GDB.
+const POLL_INTERVAL: u64 = 0x10000;
+const INTERRUPT: u8 = 0x03;
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const SUPPORTED: &str = "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+;swbreak+";
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nand2tetris.hack">
+    <reg name="a" bitsize="16" type="int16"/>
+    <reg name="d" bitsize="16" type="int16"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+const MONITOR_HELP: &str = "\
+symbol NAME   show the address of the symbol in the .sym file
+reset         set PC to 0
+";
+
+/// The input from GDB.
+#[derive(Debug, PartialEq, Eq)]
+enum Received {
+    Packet(String),
+    /// GDB asks to send the last reply again.
+    Retransmit,
+    Corrupted,
+    Interrupt,
+    Closed,
+}
+
+/// The server of the GDB remote serial protocol.
+///
+/// The registers are A, D and PC. ROM word n is at the bytes 2n and 2n + 1 in little endian, and
+/// RAM is from `RAM_BASE` in the same way, so PC is a byte address as well. The breakpoints are
+/// on ROM, and the watchpoints are on RAM.
+pub struct GdbStub<'a> {
+    computer: &'a mut Computer,
+    symbols: &'a Symbols,
+    /// The breakpoints by ROM word address.
+    breakpoints: BTreeSet<u16>,
+    /// The watchpoints by RAM word address.
+    watchpoints: BTreeMap<u16, Watch>,
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(computer: &'a mut Computer, symbols: &'a Symbols) -> Self {
+        GdbStub {
+            computer,
+            symbols,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    /// Accept a connection from GDB and serve it until it detaches.
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        self.session(&stream, &stream, || is_interrupted(&stream))
+    }
+
+    /// Serve the packets. `poll` checks the interrupt while the program is running.
+    pub fn session<R, W, F>(&mut self, mut src: R, mut dst: W, mut poll: F) -> io::Result<()>
+    where
+        R: Read,
+        W: Write,
+        F: FnMut() -> bool,
+    {
+        let mut no_ack = false;
+        let mut last = Vec::new();
+        loop {
+            let packet = match read_packet(&mut src)? {
+                Received::Packet(packet) => packet,
+                Received::Retransmit => {
+                    dst.write_all(&last)?;
+                    continue;
+                }
+                Received::Corrupted => {
+                    dst.write_all(b"-")?;
+                    continue;
+                }
+                // The program is not running.
+                Received::Interrupt => continue,
+                Received::Closed => return Ok(()),
+            };
+            if !no_ack {
+                dst.write_all(b"+")?;
+            }
+
+            let reply = match packet.as_str() {
+                "k" => return Ok(()),
+                "QStartNoAckMode" => {
+                    no_ack = true;
+                    "OK".to_string()
+                }
+                _ => self.handle(&packet, &mut poll),
+            };
+            last = frame(&reply);
+            dst.write_all(&last)?;
+            dst.flush()?;
+
+            if packet.starts_with('D') {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reply to the packet. The empty reply means unsupported.
+    fn handle<F: FnMut() -> bool>(&mut self, packet: &str, poll: &mut F) -> String {
+        if packet.starts_with("qSupported") {
+            return SUPPORTED.to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return read_chunk(TARGET_XML, range).unwrap_or_else(error);
+        }
+        if let Some(command) = packet.strip_prefix("qRcmd,") {
+            return self.monitor(command).unwrap_or_else(error);
+        }
+
+        let args = packet.get(1..).unwrap_or_default();
+        let reply = match packet.get(..1).unwrap_or_default() {
+            "?" => Some(stop(SIGTRAP)),
+            "g" => Some(
+                self.registers()
+                    .iter()
+                    .map(|register| hex_word(*register))
+                    .collect(),
+            ),
+            "G" => self.write_registers(args),
+            "p" => parse_hex(args)
+                .and_then(|n| self.registers().get(n as usize).copied())
+                .map(hex_word),
+            "P" => args.split_once('=').and_then(|(n, value)| {
+                let value = parse_word(value)?;
+                self.write_register(parse_hex(n)?, value)
+            }),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" | "z" => self.set_point(packet.starts_with('Z'), args),
+            "c" | "s" => {
+                if !args.is_empty() {
+                    let pc = parse_hex(args)
+                        .and_then(|pc| u16::try_from(pc).ok())
+                        .and_then(|pc| self.write_register(2, pc));
+                    if pc.is_none() {
+                        return error();
+                    }
+                }
+                let limit = if packet.starts_with('s') { 1 } else { u64::MAX };
+                Some(self.resume(limit, poll))
+            }
+            "H" => Some("OK".to_string()),
+            "D" => Some("OK".to_string()),
+            _ => Some(String::new()),
+        };
+
+        reply.unwrap_or_else(error)
+    }
+
+    /// A, D, and PC in bytes.
+    fn registers(&self) -> [u16; 3] {
+        [self.computer.a, self.computer.d, self.computer.pc * 2]
+    }
+
+    fn write_register(&mut self, n: u32, value: u16) -> Option<String> {
+        match n {
+            0 => self.computer.a = value,
+            1 => self.computer.d = value,
+            2 if value % 2 == 0 && (value / 2) < ROM_SIZE as u16 => self.computer.pc = value / 2,
+            _ => return None,
+        }
+        Some("OK".to_string())
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        if args.len() != 12 {
+            return None;
+        }
+        let values: Vec<u16> = (0..3)
+            .map(|i| parse_word(args.get(i * 4..i * 4 + 4)?))
+            .collect::<Option<_>>()?;
+        // Check PC first to change nothing on the error.
+        if values[2] % 2 != 0 || ROM_SIZE as u16 <= values[2] / 2 {
+            return None;
+        }
+        for (n, value) in values.into_iter().enumerate() {
+            self.write_register(n as u32, value)?;
+        }
+        Some("OK".to_string())
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (address, len) = args.split_once(',')?;
+        let (address, len) = (parse_hex(address)?, parse_hex(len)?);
+        (address..address.checked_add(len)?)
+            .map(|address| self.read_byte(address).map(|byte| format!("{:02x}", byte)))
+            .collect()
+    }
+
+    /// Write the bytes into RAM. ROM is read-only.
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (target, data) = args.split_once(':')?;
+        let (address, len) = target.split_once(',')?;
+        let (address, len) = (parse_hex(address)?, parse_hex(len)?);
+        let bytes = decode_hex(data)?;
+        if bytes.len() != len as usize {
+            return None;
+        }
+        let words = (address..address.checked_add(len)?).map(ram_word);
+        if words.clone().any(|word| word.is_none()) {
+            return None;
+        }
+
+        for (address, byte) in (address..).zip(bytes) {
+            let word = &mut self.computer.ram_mut()[ram_word(address)? as usize];
+            *word = if address % 2 == 0 {
+                *word & 0xFF00 | byte as u16
+            } else {
+                *word & 0x00FF | (byte as u16) << 8
+            };
+        }
+        Some("OK".to_string())
+    }
+
+    fn read_byte(&self, address: u32) -> Option<u8> {
+        let word = match ram_word(address) {
+            Some(word) => self.computer.ram()[word as usize],
+            None => *self.computer.rom().get(address as usize / 2)?,
+        };
+        Some(if address % 2 == 0 {
+            word as u8
+        } else {
+            (word >> 8) as u8
+        })
+    }
+
+    /// Insert or remove the breakpoint or the watchpoint, like `0,1a,2` for the breakpoint at
+    /// ROM[13].
+    fn set_point(&mut self, is_insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let address = parse_hex(fields.next()?)?;
+        let len = parse_hex(fields.next()?.split(';').next()?)?;
+
+        let watch = match kind {
+            // The software and the hardware breakpoints are the same.
+            "0" | "1" => {
+                if address % 2 != 0 || ROM_SIZE as u32 <= address / 2 {
+                    return None;
+                }
+                let address = (address / 2) as u16;
+                if is_insert {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => Watch::Write,
+            "3" => Watch::Read,
+            "4" => Watch::Access,
+            _ => return Some(String::new()),
+        };
+
+        let last = address.checked_add(len.max(1) - 1)?;
+        let (first, last) = (ram_word(address)?, ram_word(last)?);
+        for word in first..=last {
+            if is_insert {
+                self.watchpoints.insert(word, watch);
+            } else {
+                self.watchpoints.remove(&word);
+            }
+        }
+        Some("OK".to_string())
+    }
+
+    /// Execute up to the limit of instructions and make the stop reply.
+    fn resume<F: FnMut() -> bool>(&mut self, limit: u64, poll: &mut F) -> String {
+        for executed in 1..=limit {
+            let state = match self.computer.step() {
+                Ok(state) => state,
+                Err(_) => return stop(SIGSEGV),
+            };
+
+            if let Some(access) = self.computer.last_access() {
+                match self.watchpoints.get(&access.address) {
+                    Some(watch) if watch.is_triggered(&access) => {
+                        let reason = match watch {
+                            Watch::Read => "rwatch",
+                            Watch::Write => "watch",
+                            Watch::Access => "awatch",
+                        };
+                        let address = RAM_BASE + access.address as u32 * 2;
+                        return format!("T{:02x}{}:{:x};", SIGTRAP, reason, address);
+                    }
+                    _ => {}
+                }
+            }
+            if self.breakpoints.contains(&self.computer.pc) {
+                return format!("T{:02x}swbreak:;", SIGTRAP);
+            }
+            // The halted program stays stopped to be inspected.
+            if state == State::Halted {
+                return stop(SIGTRAP);
+            }
+            if executed % POLL_INTERVAL == 0 && poll() {
+                return stop(SIGINT);
+            }
+        }
+
+        stop(SIGTRAP)
+    }
+
+    /// Run the `monitor` command in hex.
+    fn monitor(&mut self, command: &str) -> Option<String> {
+        let command = String::from_utf8(decode_hex(command)?).ok()?;
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let output = match words[..] {
+            ["symbol", name] | ["sym", name] => match self.symbols.get(name) {
+                Some((address, SymbolKind::Label)) => format!(
+                    "{} is ROM[{}] at {:#x}\n",
+                    name,
+                    address,
+                    address as u32 * 2
+                ),
+                Some((address, SymbolKind::Constant)) => format!("{} is {}\n", name, address),
+                Some((address, _)) => format!(
+                    "{} is RAM[{}] at {:#x}\n",
+                    name,
+                    address,
+                    RAM_BASE + address as u32 * 2
+                ),
+                None => format!("{} is not defined\n", name),
+            },
+            ["reset"] => {
+                self.computer.pc = 0;
+                "PC is 0\n".to_string()
+            }
+            _ => MONITOR_HELP.to_string(),
+        };
+
+        Some(encode_hex(output.as_bytes()))
+    }
+}
+
+/// The word address of the RAM byte address.
+fn ram_word(address: u32) -> Option<u16> {
+    let word = address.checked_sub(RAM_BASE)? / 2;
+    if MEMORY_SIZE <= word as usize {
+        return None;
+    }
+    Some(word as u16)
+}
+
+fn read_packet<R: Read>(src: &mut R) -> io::Result<Received> {
+    loop {
+        match read_byte(src)? {
+            None => return Ok(Received::Closed),
+            Some(b'$') => break,
+            Some(b'-') => return Ok(Received::Retransmit),
+            Some(INTERRUPT) => return Ok(Received::Interrupt),
+            // The acknowledgements.
+            Some(_) => {}
+        }
+    }
+
+    let mut data = Vec::new();
+    loop {
+        match read_byte(src)? {
+            None => return Ok(Received::Closed),
+            Some(b'#') => break,
+            Some(byte) => data.push(byte),
+        }
+    }
+    let mut checksum = [0; 2];
+    if src.read_exact(&mut checksum).is_err() {
+        return Ok(Received::Closed);
+    }
+
+    let expected = std::str::from_utf8(&checksum)
+        .ok()
+        .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+    if expected != Some(checksum_of(&data)) {
+        return Ok(Received::Corrupted);
+    }
+    Ok(Received::Packet(
+        String::from_utf8_lossy(&data).into_owned(),
+    ))
+}
+
+fn read_byte<R: Read>(src: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match src.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Check GDB sent the interrupt without blocking. A closed connection stops the program too.
+///
+/// Only the interrupt is consumed, and any other byte is left for the packet reader.
+fn is_interrupted(stream: &TcpStream) -> bool {
+    let mut byte = [0];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let peeked = stream.peek(&mut byte);
+    let _ = stream.set_nonblocking(false);
+    match peeked {
+        Ok(0) => true,
+        Ok(_) if byte[0] == INTERRUPT => {
+            let mut reader = stream;
+            let _ = reader.read(&mut byte);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Frame the reply like `$OK#9a`.
+fn frame(reply: &str) -> Vec<u8> {
+    format!("${}#{:02x}", reply, checksum_of(reply.as_bytes())).into_bytes()
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn stop(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+/// Reply the part of the document for `offset,length`.
+fn read_chunk(document: &str, range: &str) -> Option<String> {
+    let (offset, len) = range.split_once(',')?;
+    let (offset, len) = (parse_hex(offset)? as usize, parse_hex(len)? as usize);
+    let chunk = document.get(offset..)?;
+    if chunk.len() <= len {
+        Some(format!("l{}", chunk))
+    } else {
+        Some(format!("m{}", chunk.get(..len)?))
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+/// Parse a word in little endian, like `3412` for 0x1234.
+fn parse_word(s: &str) -> Option<u16> {
+    match decode_hex(s)?[..] {
+        [low, high] => Some(u16::from_le_bytes([low, high])),
+        _ => None,
+    }
+}
+
+fn hex_word(word: u16) -> String {
+    encode_hex(&word.to_le_bytes())
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembly::tests::{symbols, PROGRAM};
+
+    /// Serve the packets and return the replies without the acknowledgements.
+    fn serve(computer: &mut Computer, packets: &[&str]) -> Vec<String> {
+        let symbols = symbols();
+        let src: Vec<u8> = packets.iter().flat_map(|packet| frame(packet)).collect();
+        let mut dst = Vec::new();
+        GdbStub::new(computer, &symbols)
+            .session(src.as_slice(), &mut dst, || false)
+            .unwrap();
+
+        let dst = String::from_utf8(dst).unwrap();
+        dst.split('$')
+            .skip(1)
+            .map(|reply| {
+                let (data, checksum) = reply.split_once('#').unwrap();
+                assert_eq!(
+                    &format!("{:02x}", checksum_of(data.as_bytes())),
+                    &checksum[..2]
+                );
+                data.to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packet_test() {
+        assert_eq!(b"$OK#9a", frame("OK").as_slice());
+        let mut src: &[u8] = b"+$m0,2#fb\x03$g#00";
+        assert_eq!(
+            Received::Packet("m0,2".to_string()),
+            read_packet(&mut src).unwrap()
+        );
+        assert_eq!(Received::Interrupt, read_packet(&mut src).unwrap());
+        assert_eq!(Received::Corrupted, read_packet(&mut src).unwrap());
+        assert_eq!(Received::Closed, read_packet(&mut src).unwrap());
+    }
+
+    #[test]
+    fn register_memory_test() {
+        let mut computer = Computer::new(&PROGRAM).unwrap();
+        computer.ram_mut()[16] = 0x1234;
+        let replies = serve(
+            &mut computer,
+            &[
+                "qSupported:multiprocess+",
+                "QStartNoAckMode",
+                "g",
+                "P1=ffff",
+                "p1",
+                "m0,4",
+                "m10020,2",
+                "M10021,1:56",
+                "m20000,2",
+                "M0,2:0000",
+                "qXfer:features:read:target.xml:0,10",
+            ],
+        );
+        assert_eq!(SUPPORTED, replies[0]);
+        assert_eq!("OK", replies[1]);
+        assert_eq!("000000000000", replies[2]);
+        assert_eq!(["OK", "ffff"], replies[3..5]);
+        assert_eq!(["1000c8fd", "3412", "OK", "E01", "E01"], replies[5..10]);
+        assert_eq!("m<?xml version=\"1", replies[10]);
+        assert_eq!(0x5634, computer.ram()[16]);
+        assert_eq!(0xFFFF, computer.d);
+    }
+
+    #[test]
+    fn execution_test() {
+        let mut computer = Computer::new(&PROGRAM).unwrap();
+        let replies = serve(
+            &mut computer,
+            &[
+                "s",
+                "Z2,10020,2",
+                "c",
+                "z2,10020,2",
+                "Z0,6,2",
+                "c",
+                "z0,6,2",
+                "c",
+                "c0",
+                "qRcmd,73796d626f6c204c4f4f50",
+                "D",
+                "g",
+            ],
+        );
+        assert_eq!(
+            vec![
+                "S05",
+                "OK",
+                "T05watch:10020;",
+                "OK",
+                "OK",
+                "T05swbreak:;",
+                "OK",
+                "S05",
+                "S05",
+                &encode_hex(b"LOOP is ROM[2] at 0x4\n"),
+                "OK",
+            ],
+            replies
+        );
+        // The session ends at the detach, after `c0` runs from the start to the halt again.
+        assert_eq!((8, 2), (computer.cycles, computer.ram()[16]));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::computer::{Computer, State};
+use crate::error::EmulatorError;
+use std::fs;
+use std::path::Path;
+
+/// The names of the special keys and their codes.
+const KEY_NAMES: [(&str, u16); 15] = [
+    ("space", 32),
+    ("newline", 128),
+    ("backspace", 129),
+    ("left", 130),
+    ("up", 131),
+    ("right", 132),
+    ("down", 133),
+    ("home", 134),
+    ("end", 135),
+    ("pageup", 136),
+    ("pagedown", 137),
+    ("insert", 138),
+    ("delete", 139),
+    ("esc", 140),
+    ("release", 0),
+];
+/// The code of F1, followed by F2 to F12.
+const F1: u16 = 141;
+
+/// The keys pressed at the given cycles.
+///
+/// Each line of the file is a time and a key, like `1000 65` or `30f up`. The time is a cycle
+/// count, or a frame number with `f`. The key is a code, a special key name like `newline` or
+/// `f1`, or a character like `A` or `'5'`. A key stays pressed until the next one, and `release`
+/// or `0` releases it. `//` starts a comment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    /// The cycles and the key codes in order of the cycles.
+    events: Vec<(u64, u16)>,
+    /// The index of the next event.
+    next: usize,
+}
+
+impl Schedule {
+    /// Parse the schedule. A frame is the cycles per frame.
+    pub fn parse(src: &str, cycles_per_frame: u64) -> Result<Self, EmulatorError> {
+        let mut events = Vec::new();
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (time, key) = match words[..] {
+                [] => continue,
+                [time, key] => (time, key),
+                _ => return Err(invalid(i + 1, "expected a time and a key")),
+            };
+
+            let cycle = match time.strip_suffix('f') {
+                Some(frame) => frame
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|frame| frame.checked_mul(cycles_per_frame)),
+                None => time.parse().ok(),
+            }
+            .ok_or_else(|| invalid(i + 1, &format!("`{}` is not a cycle or a frame", time)))?;
+            let code =
+                parse_key(key).ok_or_else(|| invalid(i + 1, &format!("unknown key `{}`", key)))?;
+            events.push((cycle, code));
+        }
+        // The stable sort keeps the later line winning at the same cycle.
+        events.sort_by_key(|(cycle, _)| *cycle);
+
+        Ok(Schedule { events, next: 0 })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, cycles_per_frame: u64) -> Result<Self, EmulatorError> {
+        Schedule::parse(&fs::read_to_string(path)?, cycles_per_frame)
+    }
+
+    /// Execute the instructions like `Computer::run`, pressing the keys on schedule.
+    pub fn run(&mut self, computer: &mut Computer, cycles: u64) -> Result<State, EmulatorError> {
+        self.run_with(computer, cycles, Computer::run)
+    }
+
+    /// Execute the instructions by the given function between the keys, like `Profiler::run`.
+    pub fn run_with<F>(
+        &mut self,
+        computer: &mut Computer,
+        cycles: u64,
+        mut run: F,
+    ) -> Result<State, EmulatorError>
+    where
+        F: FnMut(&mut Computer, u64) -> Result<State, EmulatorError>,
+    {
+        let end = computer.cycles.saturating_add(cycles);
+        loop {
+            self.press_due(computer);
+            let until = match self.events.get(self.next) {
+                Some((cycle, _)) => end.min(*cycle),
+                None => end,
+            };
+            if run(computer, until - computer.cycles)? == State::Halted {
+                return Ok(State::Halted);
+            }
+            if end <= computer.cycles {
+                return Ok(State::Running);
+            }
+        }
+    }
+
+    /// Write the keys whose cycles have come into the keyboard.
+    fn press_due(&mut self, computer: &mut Computer) {
+        while let Some((cycle, code)) = self.events.get(self.next) {
+            if computer.cycles < *cycle {
+                break;
+            }
+            computer.set_key(*code);
+            self.next += 1;
+        }
+    }
+}
+
+/// Parse a key code, a key name or a character.
+fn parse_key(key: &str) -> Option<u16> {
+    if let Ok(code) = key.parse() {
+        return Some(code);
+    }
+    if let Some((_, code)) = KEY_NAMES.iter().find(|(name, _)| *name == key) {
+        return Some(*code);
+    }
+    if let Some(n) = key.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()) {
+        return (1..=12).contains(&n).then(|| F1 + n - 1);
+    }
+
+    let key = key
+        .strip_prefix('\'')
+        .and_then(|key| key.strip_suffix('\''))
+        .unwrap_or(key);
+    match key.as_bytes() {
+        [c @ 0x21..=0x7E] => Some(*c as u16),
+        _ => None,
+    }
+}
+
+fn invalid(line: usize, message: &str) -> EmulatorError {
+    EmulatorError::InvalidSchedule {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let src = "// Type A, wait and press up.\n\
+                   1000 A\n\
+                   1200 release\n\
+                   2f up // The frame is 1000 cycles.\n\
+                   \n\
+                   0 '5'\n\
+                   1000 66\n\
+                   3f f12\n";
+        let schedule = Schedule::parse(src, 1000).unwrap();
+        assert_eq!(
+            vec![
+                (0, 53),
+                (1000, 65),
+                (1000, 66),
+                (1200, 0),
+                (2000, 131),
+                (3000, 152)
+            ],
+            schedule.events
+        );
+
+        assert_eq!(Some(128), parse_key("newline"));
+        assert_eq!(Some(130), parse_key("left"));
+        assert_eq!(Some(141), parse_key("f1"));
+        assert_eq!(Some(102), parse_key("f"));
+        assert_eq!(None, parse_key("f13"));
+        assert_eq!(None, parse_key("'ab'"));
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let error = |src| match Schedule::parse(src, 1000) {
+            Err(EmulatorError::InvalidSchedule { line, .. }) => line,
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!(2, error("0 A\n100\n"));
+        assert_eq!(1, error("x A\n"));
+        assert_eq!(1, error("0 spaces\n"));
+        assert_eq!(1, error("0 A B\n"));
+    }
+
+    #[test]
+    fn run_test() {
+        // (LOOP) @KBD; D=M; @R0; M=D; @LOOP; 0;JMP
+        let program = [0x6000, 0xFC10, 0, 0xE308, 0, 0xEA87];
+        let mut computer = Computer::new(&program).unwrap();
+        let mut schedule = Schedule::parse("3 A\n10 release\n", 1000).unwrap();
+
+        // The key is pressed before the 4th instruction, after @KBD and D=M read it.
+        assert_eq!(State::Running, schedule.run(&mut computer, 4).unwrap());
+        assert_eq!((65, 0), (computer.ram()[0x6000], computer.ram()[0]));
+        assert_eq!(State::Running, schedule.run(&mut computer, 6).unwrap());
+        assert_eq!(65, computer.ram()[0]);
+        assert_eq!(State::Running, schedule.run(&mut computer, 6).unwrap());
+        assert_eq!((0, 0), (computer.ram()[0x6000], computer.ram()[0]));
+        assert_eq!(16, computer.cycles);
+    }
+}
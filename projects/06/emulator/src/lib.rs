@@ -0,0 +1,15 @@
+pub mod computer;
+pub mod debugger;
+pub mod disassembly;
+pub mod error;
+pub mod gdbstub;
+pub mod keyboard;
+pub mod profiler;
+pub mod rom;
+pub mod runner;
+pub mod screen;
+pub mod script;
+pub mod snapshot;
+pub mod tui;
+
+pub use computer::{Computer, State};
@@ -0,0 +1,317 @@
+use emulator::debugger::Debugger;
+use emulator::disassembly::Symbols;
+use emulator::error::EmulatorError;
+use emulator::gdbstub::GdbStub;
+use emulator::keyboard::Schedule;
+use emulator::profiler::Profiler;
+use emulator::tui::{self, Glyphs};
+use emulator::{rom, runner, screen, snapshot, Computer, State};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::io::{Error, ErrorKind};
+use std::net::TcpListener;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// The RAM shown after the run if no range is given, R0 to R15.
+const DEFAULT_RANGE: Range<usize> = 0..16;
+/// The instructions executed per frame of the terminal UI, about 3M per second.
+const DEFAULT_CYCLES_PER_FRAME: u64 = 100_000;
+
+fn main() -> Result<(), std::io::Error> {
+    let mut hack_path = None;
+    let mut cycles = None;
+    let mut range = DEFAULT_RANGE;
+    let mut screen_path = None;
+    let mut screen_at: Vec<u64> = Vec::new();
+    let mut tui = false;
+    let mut debug = false;
+    let mut gdb_port = None;
+    let mut glyphs = Glyphs::Braille;
+    let mut sym_path = None;
+    let mut keys_path = None;
+    let mut profile = false;
+    let mut init_path = None;
+    let mut snapshot_path = None;
+    let mut collapsed_path = None;
+    let mut cycles_per_frame = DEFAULT_CYCLES_PER_FRAME;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "--cycles" => {
+                cycles = Some(args.next().and_then(|n| n.parse().ok()).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "cycles must be a number")
+                })?)
+            }
+            "-r" | "--ram" => {
+                range = args.next().and_then(|r| parse_range(&r)).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "range must be like `0..16`")
+                })?
+            }
+            "-S" | "--screen" => {
+                screen_path = Some(args.next().map(PathBuf::from).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "screen needs a .pbm or .png path")
+                })?)
+            }
+            "--screen-at" => {
+                screen_at = args
+                    .next()
+                    .and_then(|s| s.split(',').map(|n| n.parse().ok()).collect())
+                    .ok_or_else(|| {
+                        Error::new(ErrorKind::InvalidInput, "screen-at must be like `100,200`")
+                    })?
+            }
+            "-t" | "--tui" => tui = true,
+            "-d" | "--debug" => debug = true,
+            "--gdb" => {
+                gdb_port = Some(args.next().and_then(|n| n.parse().ok()).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "gdb needs a port number")
+                })?)
+            }
+            "--half-block" => glyphs = Glyphs::HalfBlock,
+            "--sym" => {
+                sym_path =
+                    Some(args.next().map(PathBuf::from).ok_or_else(|| {
+                        Error::new(ErrorKind::InvalidInput, "sym needs a .sym path")
+                    })?)
+            }
+            "-k" | "--keys" => {
+                keys_path = Some(args.next().map(PathBuf::from).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "keys needs a schedule path")
+                })?)
+            }
+            "-p" | "--profile" => profile = true,
+            "-i" | "--init" => {
+                init_path = Some(args.next().map(PathBuf::from).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "init needs a RAM contents path")
+                })?)
+            }
+            "-s" | "--snapshot" => {
+                snapshot_path = Some(args.next().map(PathBuf::from).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "snapshot needs an output path")
+                })?)
+            }
+            "--collapsed" => {
+                collapsed_path = Some(args.next().map(PathBuf::from).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "collapsed needs an output path")
+                })?)
+            }
+            "--speed" => {
+                cycles_per_frame = args.next().and_then(|n| n.parse().ok()).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "speed must be cycles per frame")
+                })?
+            }
+            _ => hack_path = Some(PathBuf::from(arg)),
+        }
+    }
+    if !screen_at.is_empty() && screen_path.is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "screen-at needs the screen path",
+        ));
+    }
+    let hack_path =
+        hack_path.ok_or_else(|| Error::new(ErrorKind::NotFound, "No .hack file is given"))?;
+
+    if hack_path.extension().is_some_and(|ext| ext == "tst") {
+        return run_script(&hack_path);
+    }
+
+    // A snapshot restores the machine state instead of the program.
+    let computer = if hack_path.extension().is_some_and(|ext| ext == "snap") {
+        snapshot::load(&hack_path)
+    } else {
+        rom::load_hack(&hack_path).and_then(|words| Computer::new(&words))
+    };
+    let mut computer = match computer {
+        Ok(computer) => computer,
+        Err(error) => report_error(error),
+    };
+    if let Some(path) = init_path {
+        if let Err(error) = snapshot::load_ram(path, &mut computer) {
+            report_error(error);
+        }
+    }
+
+    if tui {
+        let symbols = load_symbols(sym_path.clone(), &hack_path);
+        if let Err(error) = tui::run(&mut computer, &symbols, glyphs, cycles_per_frame) {
+            report_run_error(error, snapshot_path.as_deref(), &computer);
+        }
+        save_snapshot(snapshot_path.as_deref(), &computer);
+        return Ok(());
+    }
+
+    // The state is saved when the debugger quits or GDB detaches.
+    if debug {
+        let symbols = load_symbols(sym_path.clone(), &hack_path);
+        let stdin = io::stdin();
+        let result = Debugger::new(&mut computer, &symbols).run(stdin.lock(), &mut io::stdout());
+        save_snapshot(snapshot_path.as_deref(), &computer);
+        return result;
+    }
+
+    if let Some(port) = gdb_port {
+        let symbols = load_symbols(sym_path.clone(), &hack_path);
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        eprintln!("waiting for GDB on {}", listener.local_addr()?);
+        let result = GdbStub::new(&mut computer, &symbols).serve(&listener);
+        save_snapshot(snapshot_path.as_deref(), &computer);
+        return result;
+    }
+
+    // The frames in the schedule are as long as the frames of the terminal UI.
+    let mut schedule = match keys_path.map(|path| Schedule::load(path, cycles_per_frame)) {
+        Some(Ok(schedule)) => schedule,
+        Some(Err(error)) => report_error(error),
+        None => Schedule::default(),
+    };
+    let mut profiler = if profile || collapsed_path.is_some() {
+        Some(Profiler::new(&load_symbols(sym_path, &hack_path)))
+    } else {
+        None
+    };
+    let mut run = |computer: &mut Computer, cycles| match &mut profiler {
+        Some(profiler) => schedule.run_with(computer, cycles, |c, n| profiler.run(c, n)),
+        None => schedule.run(computer, cycles),
+    };
+
+    // Save the screen at each cycle count into the path suffixed by it, like `rect-100.png`.
+    screen_at.sort_unstable();
+    for at in screen_at.iter() {
+        let at_cycles = at.saturating_sub(computer.cycles);
+        if let Err(error) = run(&mut computer, at_cycles) {
+            report_run_error(error, snapshot_path.as_deref(), &computer);
+        }
+        let path = numbered_path(screen_path.as_ref().unwrap(), *at);
+        if let Err(error) = screen::save(path, computer.ram()) {
+            report_error(error);
+        }
+    }
+
+    // Run until halt unless the cycles are given, or until the last screen is saved.
+    let state = match cycles.or_else(|| screen_at.last().copied()) {
+        Some(cycles) => {
+            let rest = cycles.saturating_sub(computer.cycles);
+            run(&mut computer, rest)
+        }
+        None => run(&mut computer, u64::MAX),
+    };
+    let state = match state {
+        Ok(state) => state,
+        Err(error) => report_run_error(error, snapshot_path.as_deref(), &computer),
+    };
+    if let (Some(path), true) = (&screen_path, screen_at.is_empty()) {
+        if let Err(error) = screen::save(path, computer.ram()) {
+            report_error(error);
+        }
+    }
+    save_snapshot(snapshot_path.as_deref(), &computer);
+
+    match state {
+        State::Halted => println!("halted after {} cycles", computer.cycles),
+        State::Running => println!("stopped after {} cycles", computer.cycles),
+    }
+    println!("A: {}, D: {}, PC: {}", computer.a, computer.d, computer.pc);
+    for address in range.filter(|a| *a < computer.ram().len()) {
+        println!("RAM[{}]: {}", address, computer.ram()[address] as i16);
+    }
+
+    if let Some(profiler) = &mut profiler {
+        if profile {
+            println!();
+            profiler.write_flat(&mut io::stdout())?;
+        }
+        if let Some(path) = collapsed_path {
+            let mut dst = BufWriter::new(File::create(path)?);
+            profiler.write_collapsed(&mut dst)?;
+            dst.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the test script, write its output file and report the differences from the compared file.
+fn run_script(path: &Path) -> Result<(), std::io::Error> {
+    let report = match runner::run_script(path) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("error: {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    };
+
+    if let Some(output_file) = &report.output_file {
+        fs::write(output_file, &report.output)?;
+    }
+
+    if report.is_success() {
+        println!("{}: comparison ended successfully", path.display());
+        return Ok(());
+    }
+
+    for mismatch in &report.mismatches {
+        eprintln!("{}\n", mismatch);
+    }
+    eprintln!(
+        "error: {} failed with {} mismatched line{}",
+        path.display(),
+        report.mismatches.len(),
+        if report.mismatches.len() == 1 {
+            ""
+        } else {
+            "s"
+        }
+    );
+    process::exit(1);
+}
+
+/// Load the symbols, which are optional unless the path is given.
+fn load_symbols(sym_path: Option<PathBuf>, hack_path: &Path) -> Symbols {
+    let sym_path = sym_path.unwrap_or_else(|| hack_path.with_extension("sym"));
+    match Symbols::load(&sym_path) {
+        Ok(symbols) => symbols,
+        Err(_) if !sym_path.exists() => Symbols::default(),
+        Err(error) => report_error(error),
+    }
+}
+
+fn save_snapshot(path: Option<&Path>, computer: &Computer) {
+    if let Some(path) = path {
+        if let Err(error) = snapshot::save(path, computer) {
+            report_error(error);
+        }
+    }
+}
+
+/// Insert the number before the extension.
+fn numbered_path(path: &Path, n: u64) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}-{}", stem, n);
+    if let Some(ext) = path.extension() {
+        name = format!("{}.{}", name, ext.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// Parse `START..END` or a single address.
+fn parse_range(s: &str) -> Option<Range<usize>> {
+    match s.split_once("..") {
+        Some((start, end)) => Some(start.parse().ok()?..end.parse().ok()?),
+        None => s.parse().ok().map(|address| address..address + 1),
+    }
+}
+
+/// Save the snapshot with PC at the failed instruction, and report the error.
+fn report_run_error(error: EmulatorError, snapshot_path: Option<&Path>, computer: &Computer) -> ! {
+    save_snapshot(snapshot_path, computer);
+    report_error(error)
+}
+
+fn report_error(error: EmulatorError) -> ! {
+    eprintln!("error: {}", error);
+    process::exit(1);
+}
@@ -0,0 +1,310 @@
+use crate::computer::{Computer, State, ROM_SIZE};
+use crate::disassembly::Symbols;
+use crate::error::EmulatorError;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::prelude::*;
+
+/// The name of the code before the first function.
+const START: &str = "(start)";
+/// The call stack node of the code outside any call.
+const ROOT: usize = 0;
+
+/// A function on the call stack.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    function: usize,
+    node: usize,
+}
+
+/// The counts of the executed instructions by ROM address, by function and by call stack.
+///
+/// The functions are the labels like `Class.function`, which the VM translator emits for each
+/// function, or all the labels if there are none. An address belongs to the function of the
+/// nearest label before it.
+///
+/// A call is a jump to the first address of a function from another one, and a jump into a
+/// function on the stack returns to it, so recursion is folded into a frame. A jump from a
+/// function to a label before the functions returns to the root, while the unlabeled code there
+/// is regarded as shared by the calls and the returns, like the routines of optimized VM
+/// translators.
+#[derive(Debug)]
+pub struct Profiler {
+    counts: Vec<u64>,
+    /// The names and the first addresses of the functions, starting with `START`.
+    functions: Vec<(String, Option<u16>)>,
+    /// The index of the function by ROM address.
+    owners: Vec<usize>,
+    is_label: Vec<bool>,
+    /// The parent node and the function of each node in the tree of the call stacks.
+    nodes: Vec<(usize, usize)>,
+    children: HashMap<(usize, usize), usize>,
+    stack: Vec<Frame>,
+    /// The counts by the call stack node and the function of the executed address.
+    stack_counts: HashMap<(usize, usize), u64>,
+    /// The count of the current key, which is added to `stack_counts` when the key changes.
+    pending: ((usize, usize), u64),
+}
+
+impl Profiler {
+    pub fn new(symbols: &Symbols) -> Self {
+        let has_functions = symbols.labels().any(|(_, label)| is_function(label));
+        let mut labels: Vec<(u16, &str)> = symbols
+            .labels()
+            .filter(|(_, label)| !has_functions || is_function(label))
+            .collect();
+        labels.sort_unstable();
+
+        let mut is_label = vec![false; ROM_SIZE];
+        for (address, _) in symbols.labels() {
+            is_label[address as usize] = true;
+        }
+        let mut functions = vec![(START.to_string(), None)];
+        let mut owners = vec![ROOT; ROM_SIZE];
+        for (address, label) in labels {
+            functions.push((label.to_string(), Some(address)));
+            owners[address as usize..].fill(functions.len() - 1);
+        }
+
+        Profiler {
+            counts: vec![0; ROM_SIZE],
+            functions,
+            owners,
+            is_label,
+            nodes: vec![(ROOT, ROOT)],
+            children: HashMap::new(),
+            stack: Vec::new(),
+            stack_counts: HashMap::new(),
+            pending: ((ROOT, ROOT), 0),
+        }
+    }
+
+    /// The executed instructions by ROM address.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Execute an instruction and count it.
+    pub fn step(&mut self, computer: &mut Computer) -> Result<State, EmulatorError> {
+        let pc = computer.pc;
+        let state = computer.step()?;
+        self.counts[pc as usize] += 1;
+
+        let node = self.stack.last().map_or(ROOT, |frame| frame.node);
+        let key = (node, self.owners[pc as usize]);
+        if self.pending.0 == key {
+            self.pending.1 += 1;
+        } else {
+            self.flush();
+            self.pending = (key, 1);
+        }
+
+        if computer.pc != pc.wrapping_add(1) {
+            self.jump(pc, computer.pc);
+        }
+
+        Ok(state)
+    }
+
+    /// Execute the instructions like `Computer::run` and count them.
+    pub fn run(&mut self, computer: &mut Computer, cycles: u64) -> Result<State, EmulatorError> {
+        for _ in 0..cycles {
+            if self.step(computer)? == State::Halted {
+                return Ok(State::Halted);
+            }
+        }
+
+        Ok(State::Running)
+    }
+
+    /// Write the counts by function, excluding and including the functions they call.
+    pub fn write_flat<W: Write>(&mut self, dst: &mut W) -> io::Result<()> {
+        let total: u64 = self.counts.iter().sum();
+        let mut flat = vec![(0, 0); self.functions.len()];
+        for (address, count) in self.counts.iter().enumerate() {
+            flat[self.owners[address]].0 += count;
+        }
+        for (functions, count) in self.stacks() {
+            let mut seen = vec![false; self.functions.len()];
+            for function in functions {
+                if !std::mem::replace(&mut seen[function], true) {
+                    flat[function].1 += count;
+                }
+            }
+        }
+
+        let mut order: Vec<usize> = (0..flat.len()).filter(|i| flat[*i].1 != 0).collect();
+        order.sort_by_key(|i| (std::cmp::Reverse(flat[*i]), *i));
+
+        let percent = |count| count as f64 * 100.0 / total.max(1) as f64;
+        writeln!(
+            dst,
+            "{:>12} {:>7} {:>12} {:>7}  function",
+            "self", "%", "inclusive", "%"
+        )?;
+        for i in order {
+            let (exclusive, inclusive) = flat[i];
+            writeln!(
+                dst,
+                "{:>12} {:>6.2}% {:>12} {:>6.2}%  {}",
+                exclusive,
+                percent(exclusive),
+                inclusive,
+                percent(inclusive),
+                self.functions[i].0
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the counts by call stack in the collapsed format of flame graph tools, like
+    /// `(start);Sys.init;Main.main 1234`.
+    pub fn write_collapsed<W: Write>(&mut self, dst: &mut W) -> io::Result<()> {
+        let mut lines = BTreeMap::new();
+        for (functions, count) in self.stacks() {
+            let names: Vec<&str> = functions
+                .iter()
+                .map(|function| self.functions[*function].0.as_str())
+                .collect();
+            *lines.entry(names.join(";")).or_insert(0) += count;
+        }
+
+        for (stack, count) in lines {
+            writeln!(dst, "{} {}", stack, count)?;
+        }
+
+        Ok(())
+    }
+
+    /// The functions from the root of each call stack to the executed one, and the counts.
+    fn stacks(&mut self) -> Vec<(Vec<usize>, u64)> {
+        self.flush();
+        self.stack_counts
+            .iter()
+            .map(|((node, owner), count)| {
+                let mut functions = Vec::new();
+                let mut node = *node;
+                loop {
+                    let (parent, function) = self.nodes[node];
+                    functions.push(function);
+                    if node == ROOT {
+                        break;
+                    }
+                    node = parent;
+                }
+                functions.reverse();
+                if functions.last() != Some(owner) {
+                    functions.push(*owner);
+                }
+                (functions, *count)
+            })
+            .collect()
+    }
+
+    fn flush(&mut self) {
+        let (key, count) = std::mem::replace(&mut self.pending, ((ROOT, ROOT), 0));
+        if count != 0 {
+            *self.stack_counts.entry(key).or_insert(0) += count;
+        }
+    }
+
+    /// Track the call stack by the jump.
+    fn jump(&mut self, from: u16, to: u16) {
+        let function = self.owners[to as usize];
+        if function == self.owners[from as usize] {
+            return;
+        }
+        if function == ROOT {
+            // The unlabeled code before the functions is the routines shared by calls and returns.
+            if self.is_label[to as usize] {
+                self.stack.clear();
+            }
+            return;
+        }
+        if let Some(i) = self
+            .stack
+            .iter()
+            .rposition(|frame| frame.function == function)
+        {
+            self.stack.truncate(i + 1);
+            return;
+        }
+        if self.functions[function].1 != Some(to) {
+            return;
+        }
+
+        let parent = self.stack.last().map_or(ROOT, |frame| frame.node);
+        let next = self.nodes.len();
+        let node = *self.children.entry((parent, function)).or_insert(next);
+        if node == next {
+            self.nodes.push((parent, function));
+        }
+        self.stack.push(Frame { function, node });
+    }
+}
+
+/// Check the label names a VM function, like `Main.main` but not `Main.main$LOOP`.
+fn is_function(label: &str) -> bool {
+    label.contains('.') && !label.contains('$')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(src: &str) -> (Computer, Symbols) {
+        let program = assembler::assemble_program(src).unwrap();
+        let symbols = Symbols::new(&program.symbol_table);
+        (Computer::new(&program.words).unwrap(), symbols)
+    }
+
+    #[test]
+    fn profile_test() {
+        // Main.main calls Math.twice twice, which returns to the address in R13.
+        let (mut computer, symbols) = program(
+            "@RET0\nD=A\n@R13\nM=D\n@Math.twice\n0;JMP\n(RET0)\n\
+             @RET1\nD=A\n@R13\nM=D\n@Math.twice\n0;JMP\n(RET1)\n\
+             (Main.main$END)\n@Main.main$END\n0;JMP\n\
+             (Math.twice)\nD=D+A\n@R13\nA=M\n0;JMP\n",
+        );
+        let mut profiler = Profiler::new(&symbols);
+        assert_eq!(State::Halted, profiler.run(&mut computer, 100).unwrap());
+        assert_eq!(2, profiler.counts()[16]);
+
+        let mut flat = Vec::new();
+        profiler.write_flat(&mut flat).unwrap();
+        assert_eq!(
+            "        self       %    inclusive       %  function\n\
+             \x20         14  63.64%           22 100.00%  (start)\n\
+             \x20          8  36.36%            8  36.36%  Math.twice\n",
+            String::from_utf8(flat).unwrap()
+        );
+
+        let mut collapsed = Vec::new();
+        profiler.write_collapsed(&mut collapsed).unwrap();
+        assert_eq!(
+            "(start) 14\n(start);Math.twice 8\n",
+            String::from_utf8(collapsed).unwrap()
+        );
+    }
+
+    #[test]
+    fn nested_call_test() {
+        // A.f calls B.g, which returns by jumping into A.f.
+        let (mut computer, symbols) = program(
+            "@A.f\n0;JMP\n(HALT)\n@HALT\n0;JMP\n\
+             (A.f)\n@R0\nM=M+1\nD=M\n@3\nD=D-A\n@HALT\nD;JGE\n@B.g\n0;JMP\n\
+             (B.g)\n@A.f\n0;JMP\n",
+        );
+        let mut profiler = Profiler::new(&symbols);
+        assert_eq!(State::Halted, profiler.run(&mut computer, 1000).unwrap());
+
+        let mut collapsed = Vec::new();
+        profiler.write_collapsed(&mut collapsed).unwrap();
+        assert_eq!(
+            "(start) 4\n(start);A.f 25\n(start);A.f;B.g 4\n",
+            String::from_utf8(collapsed).unwrap()
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use crate::error::EmulatorError;
+use std::fs;
+use std::path::Path;
+
+/// Read the words from the text format of .hack files. Blank lines are ignored.
+pub fn read_hack(src: &str) -> Result<Vec<u16>, EmulatorError> {
+    src.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line_number, line)| {
+            if line.len() != 16 || line.chars().any(|c| c != '0' && c != '1') {
+                return Err(EmulatorError::InvalidWord(line_number));
+            }
+            Ok(u16::from_str_radix(line, 2).unwrap())
+        })
+        .collect()
+}
+
+/// Read the .hack file.
+pub fn load_hack<P: AsRef<Path>>(path: P) -> Result<Vec<u16>, EmulatorError> {
+    read_hack(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_hack_test() {
+        let src = "0000000000000010\n1110110000010000\n\n";
+        assert_eq!(vec![2, 0xEC10], read_hack(src).unwrap());
+
+        assert!(matches!(
+            read_hack("0000000000000010\n111011000001000"),
+            Err(EmulatorError::InvalidWord(2))
+        ));
+        assert!(matches!(
+            read_hack("+000000000000010"),
+            Err(EmulatorError::InvalidWord(1))
+        ));
+    }
+}
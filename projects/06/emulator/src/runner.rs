@@ -0,0 +1,426 @@
+use crate::computer::{Computer, ROM_SIZE};
+use crate::error::EmulatorError;
+use crate::rom;
+use crate::script::{self, Column, Command, Variable};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The only chip which the scripts for the hardware simulator can load.
+const COMPUTER_CHIP: &str = "Computer.hdl";
+
+/// The result of running a test script.
+#[derive(Debug)]
+pub struct Report {
+    /// The lines written by `output-list` and `output`.
+    pub output: String,
+    /// The path given by `output-file`.
+    pub output_file: Option<PathBuf>,
+    /// The differences from the file given by `compare-to`.
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    pub fn is_success(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// A line of the output which differs from the compared file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// 1-origin line number.
+    pub line: usize,
+    /// `None` if the output has more lines than the compared file.
+    pub expected: Option<String>,
+    /// `None` if the output ends before the line.
+    pub actual: Option<String>,
+    /// The names of the columns which differ.
+    pub columns: Vec<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => {
+                write!(f, "line {}: ", self.line)?;
+                if self.columns.is_empty() {
+                    writeln!(f, "the layout differs")?;
+                } else {
+                    writeln!(f, "{} differs", self.columns.join(", "))?;
+                }
+                writeln!(f, "  expected: {}", expected)?;
+                write!(f, "  actual:   {}", actual)
+            }
+            (Some(expected), None) => {
+                writeln!(f, "line {}: the output is missing", self.line)?;
+                write!(f, "  expected: {}", expected)
+            }
+            (None, Some(actual)) => {
+                writeln!(f, "line {}: the output is not expected", self.line)?;
+                write!(f, "  actual:   {}", actual)
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+/// Run the test script for the CPU emulator, or for `Computer.hdl` of the hardware simulator.
+///
+/// The paths in the script are relative to the script. `load X.hack` assembles `X.asm` if the
+/// .hack file does not exist. The output file is not written.
+pub fn run_script<P: AsRef<Path>>(path: P) -> Result<Report, EmulatorError> {
+    let path = path.as_ref();
+    let commands = script::parse(&read(path)?)?;
+    let mut runner = Runner::new(path.parent().unwrap_or_else(|| Path::new("")));
+    runner.execute(&commands)?;
+
+    let mismatches = match &runner.compare_file {
+        Some(compare_file) => compare(&read(compare_file)?, &runner.output),
+        None => Vec::new(),
+    };
+    let output = runner
+        .output
+        .iter()
+        .map(|(line, _)| format!("{}\n", line))
+        .collect();
+
+    Ok(Report {
+        output,
+        output_file: runner.output_file,
+        mismatches,
+    })
+}
+
+struct Runner {
+    dir: PathBuf,
+    computer: Computer,
+    reset: bool,
+    time: u64,
+    /// Whether the clock is in the middle of a cycle after `tick`.
+    is_tick: bool,
+    columns: Vec<Column>,
+    /// The output lines and the names of their columns.
+    output: Vec<(String, Vec<String>)>,
+    output_file: Option<PathBuf>,
+    compare_file: Option<PathBuf>,
+}
+
+impl Runner {
+    fn new(dir: &Path) -> Self {
+        Runner {
+            dir: dir.to_path_buf(),
+            computer: Computer::new(&[]).unwrap(),
+            reset: false,
+            time: 0,
+            is_tick: false,
+            columns: Vec::new(),
+            output: Vec::new(),
+            output_file: None,
+            compare_file: None,
+        }
+    }
+
+    fn execute(&mut self, commands: &[Command]) -> Result<(), EmulatorError> {
+        for command in commands {
+            match command {
+                Command::Load(name) if name == COMPUTER_CHIP => {
+                    self.computer = Computer::new(&[])?;
+                }
+                Command::Load(name) | Command::LoadRom(name) => {
+                    self.computer = Computer::new(&self.load(name)?)?;
+                }
+                Command::OutputFile(name) => self.output_file = Some(self.dir.join(name)),
+                Command::CompareTo(name) => self.compare_file = Some(self.dir.join(name)),
+                Command::OutputList(columns) => {
+                    self.columns = columns.clone();
+                    let headers: Vec<String> = columns.iter().map(Column::header).collect();
+                    self.write_line(&headers);
+                }
+                Command::Set(variable, value) => self.set(*variable, *value),
+                Command::Repeat(count, body) => {
+                    for _ in 0..*count {
+                        self.execute(body)?;
+                    }
+                }
+                Command::Tick => self.is_tick = true,
+                Command::Tock => self.tock()?,
+                Command::TickTock => {
+                    self.is_tick = true;
+                    self.tock()?;
+                }
+                Command::Output => {
+                    let cells: Vec<String> = self
+                        .columns
+                        .iter()
+                        .map(|column| column.cell(&self.value(column)))
+                        .collect();
+                    self.write_line(&cells);
+                }
+                Command::Echo => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load the program in .hack or .asm.
+    fn load(&self, name: &str) -> Result<Vec<u16>, EmulatorError> {
+        let path = self.dir.join(name);
+        let is_hack = path.extension().is_some_and(|ext| ext == "hack");
+        if is_hack && path.exists() {
+            return rom::read_hack(&read(&path)?);
+        }
+
+        let asm_path = path.with_extension("asm");
+        if !is_hack && path != asm_path {
+            return Err(EmulatorError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is neither .hack nor .asm", path.display()),
+            )));
+        }
+
+        let file_name = asm_path.to_string_lossy();
+        assembler::assemble(&read(&asm_path)?).map_err(|errors| {
+            let diagnostics: Vec<String> = errors
+                .into_iter()
+                .map(|error| error.in_file(&file_name).to_string())
+                .collect();
+            EmulatorError::Assemble(diagnostics.join("\n\n"))
+        })
+    }
+
+    fn set(&mut self, variable: Variable, value: u16) {
+        match variable {
+            Variable::Memory(address) => self.computer.ram_mut()[address as usize] = value,
+            Variable::A => self.computer.a = value,
+            Variable::D => self.computer.d = value,
+            Variable::Pc => self.computer.pc = value & (ROM_SIZE as u16 - 1),
+            Variable::Reset => self.reset = value != 0,
+            Variable::Time => unreachable!("time cannot be set"),
+        }
+    }
+
+    /// Execute an instruction at the end of the cycle. The reset takes effect after it.
+    fn tock(&mut self) -> Result<(), EmulatorError> {
+        self.computer.step()?;
+        if self.reset {
+            self.computer.pc = 0;
+        }
+        self.time += 1;
+        self.is_tick = false;
+
+        Ok(())
+    }
+
+    fn value(&self, column: &Column) -> String {
+        let value = match column.variable {
+            Variable::Memory(address) => self.computer.ram()[address as usize],
+            Variable::A => self.computer.a,
+            Variable::D => self.computer.d,
+            Variable::Pc => self.computer.pc,
+            Variable::Reset => self.reset as u16,
+            Variable::Time => {
+                return format!("{}{}", self.time, if self.is_tick { "+" } else { "" });
+            }
+        };
+
+        let digits = match column.format {
+            'B' => format!("{:016b}", value),
+            'X' => format!("{:04X}", value),
+            _ => return (value as i16).to_string(),
+        };
+        let skip = digits.len().saturating_sub(column.width);
+        digits[skip..].to_string()
+    }
+
+    fn write_line(&mut self, cells: &[String]) {
+        let names = self.columns.iter().map(|c| c.name.clone()).collect();
+        self.output.push((format!("|{}|", cells.join("|")), names));
+    }
+}
+
+/// Compare the output with the expected lines. `*` in the expected lines matches any character.
+fn compare(expected: &str, output: &[(String, Vec<String>)]) -> Vec<Mismatch> {
+    let mut expected: Vec<&str> = expected.lines().map(str::trim_end).collect();
+    while expected.last() == Some(&"") {
+        expected.pop();
+    }
+
+    (0..expected.len().max(output.len()))
+        .filter_map(|i| {
+            let expected = expected.get(i).copied();
+            let output = output.get(i);
+            let columns = match (expected, output) {
+                (Some(expected), Some((actual, _))) if matches(expected, actual) => return None,
+                (Some(expected), Some((actual, names))) => {
+                    differing_columns(expected, actual, names)
+                }
+                _ => Vec::new(),
+            };
+
+            Some(Mismatch {
+                line: i + 1,
+                expected: expected.map(str::to_string),
+                actual: output.map(|(line, _)| line.clone()),
+                columns,
+            })
+        })
+        .collect()
+}
+
+fn matches(expected: &str, actual: &str) -> bool {
+    expected.chars().count() == actual.chars().count()
+        && expected
+            .chars()
+            .zip(actual.chars())
+            .all(|(e, a)| e == '*' || e == a)
+}
+
+/// The names of the columns which differ. Nothing is returned if the columns are not aligned.
+fn differing_columns(expected: &str, actual: &str, names: &[String]) -> Vec<String> {
+    let expected: Vec<&str> = expected.split('|').collect();
+    let actual: Vec<&str> = actual.split('|').collect();
+    if expected.len() != actual.len() || expected.len() != names.len() + 2 {
+        return Vec::new();
+    }
+
+    // The cells are between the leading and trailing separators.
+    expected[1..]
+        .iter()
+        .zip(&actual[1..])
+        .zip(names)
+        .filter(|((e, a), _)| !matches(e, a))
+        .map(|(_, name)| name.clone())
+        .collect()
+}
+
+fn read(path: &Path) -> Result<String, EmulatorError> {
+    fs::read_to_string(path).map_err(|e| {
+        EmulatorError::Io(io::Error::new(
+            e.kind(),
+            format!("{}: {}", path.display(), e),
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// Write the files into a new temporary directory.
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("emulator-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn run_script_test() {
+        let tst = "load Double.asm,\n\
+                   output-file Double.out,\n\
+                   compare-to Double.cmp,\n\
+                   output-list RAM[0]%D2.6.2 RAM[1]%D2.6.2 D%X1.4.1;\n\
+                   set RAM[0] 21;\n\
+                   repeat 5 { ticktock; }\n\
+                   output;\n\
+                   set PC 0, set RAM[0] -3, ticktock, ticktock, ticktock, ticktock, output;";
+        let asm = "@R0\nD=M\nD=D+M\n@R1\nM=D\n(END)\n@END\n0;JMP";
+        let cmp = "|  RAM[0]  |  RAM[1]  |  D   |\n\
+                   |      21  |      42  | 002A |\n\
+                   |      -3  |       0  | FFFA |\n";
+        let dir = write_files(
+            "run",
+            &[
+                ("Double.tst", tst),
+                ("Double.asm", asm),
+                ("Double.cmp", cmp),
+            ],
+        );
+
+        let report = run_script(dir.join("Double.tst")).unwrap();
+        assert_eq!(
+            "|  RAM[0]  |  RAM[1]  |  D   |\n\
+             |      21  |      42  | 002A |\n\
+             |      -3  |      42  | FFFA |\n",
+            report.output
+        );
+        assert_eq!(Some(dir.join("Double.out")), report.output_file);
+        assert_eq!(
+            vec![Mismatch {
+                line: 3,
+                expected: Some("|      -3  |       0  | FFFA |".to_string()),
+                actual: Some("|      -3  |      42  | FFFA |".to_string()),
+                columns: vec!["RAM[1]".to_string()],
+            }],
+            report.mismatches
+        );
+        assert_eq!(
+            "line 3: RAM[1] differs\n\
+             \x20 expected: |      -3  |       0  | FFFA |\n\
+             \x20 actual:   |      -3  |      42  | FFFA |",
+            report.mismatches[0].to_string()
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn computer_chip_test() {
+        let tst = "load Computer.hdl,\n\
+                   output-list time%S1.4.1 reset%B2.1.2 ARegister[]%D1.7.1 PC[]%D0.4.0;\n\
+                   ROM32K load Loop.hack,\n\
+                   output;\n\
+                   tick, output, tock, output;\n\
+                   set reset 1, tick, tock, output;";
+        // @7; D=A; (LOOP) @2; 0;JMP
+        let hack = "0000000000000111\n1110110000010000\n0000000000000010\n1110101010000111\n";
+        let dir = write_files("chip", &[("Loop.tst", tst), ("Loop.hack", hack)]);
+
+        let report = run_script(dir.join("Loop.tst")).unwrap();
+        assert_eq!(
+            "| time |reset|ARegister|PC[]|\n\
+             | 0    |  0  |       0 |   0|\n\
+             | 0+   |  0  |       0 |   0|\n\
+             | 1    |  0  |       7 |   1|\n\
+             | 2    |  1  |       7 |   0|\n",
+            report.output
+        );
+        assert!(report.is_success());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compare_test() {
+        let output = vec![
+            (
+                "| a | b |".to_string(),
+                vec!["a".to_string(), "b".to_string()],
+            ),
+            (
+                "| 1 | 2 |".to_string(),
+                vec!["a".to_string(), "b".to_string()],
+            ),
+        ];
+        assert!(compare("| a | b |\n| * | 2 |\n\n", &output).is_empty());
+
+        let mismatches = compare("| a | b |\n", &output);
+        assert_eq!(1, mismatches.len());
+        assert_eq!(
+            (2, None),
+            (mismatches[0].line, mismatches[0].expected.as_deref())
+        );
+
+        let mismatches = compare("| a | b |\n| 1 | 2 |\n| 3 | 4 |", &output);
+        assert_eq!(
+            (3, None),
+            (mismatches[0].line, mismatches[0].actual.as_deref())
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::computer::SCREEN;
+use crate::error::EmulatorError;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+
+pub const WIDTH: usize = 512;
+pub const HEIGHT: usize = 256;
+/// The number of words in a row of the screen.
+const ROW_WORDS: usize = WIDTH / 16;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// The maximum length of a stored deflate block.
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Check the pixel is black. The least significant bit of a word is the leftmost pixel.
+pub fn is_black(ram: &[u16], x: usize, y: usize) -> bool {
+    let word = ram[SCREEN as usize + y * ROW_WORDS + x / 16];
+    word & (1 << (x % 16)) != 0
+}
+
+/// Pack a row into bytes, the leftmost pixel in the most significant bit and black as 1.
+fn pack_row(ram: &[u16], y: usize) -> Vec<u8> {
+    (0..WIDTH / 8)
+        .map(|i| {
+            (0..8).fold(0, |byte, bit| {
+                byte << 1 | is_black(ram, i * 8 + bit, y) as u8
+            })
+        })
+        .collect()
+}
+
+/// Write the screen in the binary PBM format.
+pub fn write_pbm<W: Write>(ram: &[u16], dst: &mut W) -> io::Result<()> {
+    write!(dst, "P4\n{} {}\n", WIDTH, HEIGHT)?;
+    for y in 0..HEIGHT {
+        dst.write_all(&pack_row(ram, y))?;
+    }
+
+    Ok(())
+}
+
+/// Write the screen in a 1-bit grayscale PNG, whose image data is deflated without compression.
+pub fn write_png<W: Write>(ram: &[u16], dst: &mut W) -> io::Result<()> {
+    dst.write_all(&PNG_SIGNATURE)?;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+    // Bit depth 1, grayscale, deflate, adaptive filtering and no interlace.
+    header.extend_from_slice(&[1, 0, 0, 0, 0]);
+    write_chunk(dst, b"IHDR", &header)?;
+
+    // Each row has the filter type none. Gray 0 is black in PNG.
+    let mut image = Vec::with_capacity(HEIGHT * (WIDTH / 8 + 1));
+    for y in 0..HEIGHT {
+        image.push(0);
+        image.extend(pack_row(ram, y).iter().map(|byte| !byte));
+    }
+    write_chunk(dst, b"IDAT", &zlib_stored(&image))?;
+
+    write_chunk(dst, b"IEND", &[])
+}
+
+/// Save the screen in the format of the extension, `pbm` or `png`.
+pub fn save<P: AsRef<Path>>(path: P, ram: &[u16]) -> Result<(), EmulatorError> {
+    let path = path.as_ref();
+    let write = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pbm") => write_pbm,
+        Some("png") => write_png,
+        _ => {
+            return Err(EmulatorError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is neither .pbm nor .png", path.display()),
+            )))
+        }
+    };
+
+    let mut dst = BufWriter::new(File::create(path)?);
+    write(ram, &mut dst)?;
+    dst.flush()?;
+
+    Ok(())
+}
+
+fn write_chunk<W: Write>(dst: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    dst.write_all(&(data.len() as u32).to_be_bytes())?;
+    dst.write_all(kind)?;
+    dst.write_all(data)?;
+
+    let crc = crc32(kind.iter().chain(data));
+    dst.write_all(&crc.to_be_bytes())
+}
+
+/// Wrap the data in a zlib stream of stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with the 32K window and no preset dictionary.
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(MAX_STORED_BLOCK).collect();
+    if blocks.is_empty() {
+        stream.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    for (i, block) in blocks.iter().enumerate() {
+        let is_last = i + 1 == blocks.len();
+        let len = block.len() as u16;
+        stream.push(is_last as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+
+    stream
+}
+
+fn crc32<'a, I: IntoIterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let crc = bytes.into_iter().fold(0xFFFF_FFFF, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                crc >> 1 ^ 0xEDB8_8320
+            }
+        })
+    });
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MODULO: u32 = 65521;
+    let (a, b) = bytes.iter().fold((1, 0), |(a, b), byte| {
+        let a = (a + *byte as u32) % MODULO;
+        (a, (b + a) % MODULO)
+    });
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::MEMORY_SIZE;
+
+    /// The RAM with the pixels (0, 0), (17, 0) and (511, 255) black.
+    fn ram() -> Vec<u16> {
+        let mut ram = vec![0; MEMORY_SIZE];
+        ram[SCREEN as usize] = 1;
+        ram[SCREEN as usize + 1] = 1 << 1;
+        ram[SCREEN as usize + 8191] = 0x8000;
+        ram
+    }
+
+    #[test]
+    fn pbm_test() {
+        let ram = ram();
+        assert!(is_black(&ram, 17, 0));
+        assert!(!is_black(&ram, 16, 0));
+
+        let mut dst = Vec::new();
+        write_pbm(&ram, &mut dst).unwrap();
+        let (header, pixels) = dst.split_at(11);
+        assert_eq!(b"P4\n512 256\n", header);
+        assert_eq!(WIDTH / 8 * HEIGHT, pixels.len());
+        assert_eq!([0x80, 0, 0x40], pixels[..3]);
+        assert_eq!(0x01, pixels[pixels.len() - 1]);
+    }
+
+    #[test]
+    fn png_test() {
+        let mut dst = Vec::new();
+        write_png(&ram(), &mut dst).unwrap();
+        assert_eq!(PNG_SIGNATURE, dst[..8]);
+        assert_eq!(b"IHDR", &dst[12..16]);
+        assert_eq!(b"IEND", &dst[dst.len() - 8..dst.len() - 4]);
+
+        // The image data is the zlib header, a stored block and the checksum.
+        let idat_len = u32::from_be_bytes([dst[33], dst[34], dst[35], dst[36]]) as usize;
+        let image_len = HEIGHT * (WIDTH / 8 + 1);
+        assert_eq!(2 + 5 + image_len + 4, idat_len);
+        assert_eq!(b"IDAT", &dst[37..41]);
+        assert_eq!([0, 0x7F, 0xFF, 0xBF], dst[48..52]);
+    }
+
+    #[test]
+    fn checksum_test() {
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+        assert_eq!(0xAE42_6082, crc32(b"IEND"));
+        assert_eq!(0x11E6_0398, adler32(b"Wikipedia"));
+        assert_eq!(
+            vec![0x78, 0x01, 1, 0, 0, 0xFF, 0xFF, 0, 0, 0, 1],
+            zlib_stored(&[])
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use crate::computer::{KBD, SCREEN};
+use crate::error::EmulatorError;
+
+/// A command of the test scripts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Load the program, or the chip such as `Computer.hdl`.
+    Load(String),
+    /// `ROM32K load` of the scripts for `Computer.hdl`.
+    LoadRom(String),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(Variable, u16),
+    Repeat(u64, Vec<Command>),
+    Tick,
+    Tock,
+    TickTock,
+    Output,
+    /// The commands for the interactive emulators which do nothing here.
+    Echo,
+}
+
+/// The machine states which the scripts read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    Memory(u16),
+    A,
+    D,
+    Pc,
+    Reset,
+    Time,
+}
+
+/// A column of `output-list` such as `RAM[0]%D2.6.2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    /// The variable name as written.
+    pub name: String,
+    pub variable: Variable,
+    /// One of `B`, `D`, `S` and `X`.
+    pub format: char,
+    pub left: usize,
+    pub width: usize,
+    pub right: usize,
+}
+
+impl Column {
+    /// The total width between the separators.
+    pub fn total_width(&self) -> usize {
+        self.left + self.width + self.right
+    }
+
+    /// The name centered in the column, or truncated if it is too long.
+    pub fn header(&self) -> String {
+        let total = self.total_width();
+        let name: String = self.name.chars().take(total).collect();
+        let left = (total - name.chars().count()) / 2;
+        format!(
+            "{:left$}{:<rest$}",
+            "",
+            name,
+            left = left,
+            rest = total - left
+        )
+    }
+
+    /// Format the value in the column.
+    pub fn cell(&self, value: &str) -> String {
+        let value = if self.format == 'S' {
+            format!("{:<1$}", value, self.width)
+        } else {
+            format!("{:>1$}", value, self.width)
+        };
+        format!(
+            "{:left$}{}{:right$}",
+            "",
+            value,
+            "",
+            left = self.left,
+            right = self.right
+        )
+    }
+}
+
+/// A token and its 1-origin line number.
+type Token = (usize, String);
+
+/// Parse the script of the CPU emulator or the hardware simulator.
+pub fn parse(src: &str) -> Result<Vec<Command>, EmulatorError> {
+    parse_block(&mut tokenize(src)?.into_iter(), None)
+}
+
+fn parse_block<I: Iterator<Item = Token>>(
+    tokens: &mut I,
+    open_line: Option<usize>,
+) -> Result<Vec<Command>, EmulatorError> {
+    let mut commands = Vec::new();
+    let mut words: Vec<Token> = Vec::new();
+    loop {
+        let (line, token) = match tokens.next() {
+            Some(token) => token,
+            None if open_line.is_none() && words.is_empty() => return Ok(commands),
+            None => {
+                let line = open_line.unwrap_or_else(|| words[0].0);
+                return Err(invalid(line, "script ends in the middle of a command"));
+            }
+        };
+
+        match token.as_str() {
+            "," | ";" | "!" => {
+                if !words.is_empty() {
+                    commands.push(parse_command(&words)?);
+                    words.clear();
+                }
+            }
+            "{" => {
+                let count = match words.as_slice() {
+                    [(_, repeat), (_, count)] if repeat == "repeat" => count
+                        .parse()
+                        .map_err(|_| invalid(line, "repeat count must be a number"))?,
+                    [(_, repeat)] if repeat == "repeat" => {
+                        return Err(invalid(line, "endless repeat is not supported"))
+                    }
+                    _ => return Err(invalid(line, "only repeat takes a block")),
+                };
+                words.clear();
+                commands.push(Command::Repeat(count, parse_block(tokens, Some(line))?));
+            }
+            "}" if open_line.is_some() && words.is_empty() => return Ok(commands),
+            "}" => return Err(invalid(line, "unexpected `}`")),
+            _ => words.push((line, token)),
+        }
+    }
+}
+
+fn parse_command(words: &[Token]) -> Result<Command, EmulatorError> {
+    let line = words[0].0;
+    let words: Vec<&str> = words.iter().map(|(_, word)| word.as_str()).collect();
+    let command = match words.as_slice() {
+        ["load", path] => Command::Load(path.to_string()),
+        ["ROM32K", "load", path] => Command::LoadRom(path.to_string()),
+        ["output-file", path] => Command::OutputFile(path.to_string()),
+        ["compare-to", path] => Command::CompareTo(path.to_string()),
+        ["output-list", columns @ ..] => Command::OutputList(
+            columns
+                .iter()
+                .map(|column| parse_column(column).ok_or_else(|| invalid(line, "invalid column")))
+                .collect::<Result<_, _>>()?,
+        ),
+        ["set", name, value] => match parse_variable(name) {
+            Some(Variable::Time) => return Err(invalid(line, "time cannot be set")),
+            Some(variable) => Command::Set(
+                variable,
+                parse_value(value).ok_or_else(|| invalid(line, "invalid value"))?,
+            ),
+            None => return Err(invalid(line, "unknown variable")),
+        },
+        ["tick"] => Command::Tick,
+        ["tock"] => Command::Tock,
+        ["ticktock"] => Command::TickTock,
+        ["output"] => Command::Output,
+        ["echo", ..] | ["clear-echo"] => Command::Echo,
+        [command, ..] => return Err(invalid(line, &format!("unknown command `{}`", command))),
+        [] => unreachable!(),
+    };
+
+    Ok(command)
+}
+
+/// Parse the column like `RAM[0]%D2.6.2`. The format is `%B1.16.1` if omitted.
+fn parse_column(s: &str) -> Option<Column> {
+    let (name, format) = s.split_once('%').unwrap_or((s, "B1.16.1"));
+    let mut chars = format.chars();
+    let kind = chars.next().filter(|c| "BDSX".contains(*c))?;
+    let widths: Vec<usize> = chars
+        .as_str()
+        .split('.')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let (left, width, right) = match widths.as_slice() {
+        [left, width, right] => (*left, *width, *right),
+        _ => return None,
+    };
+
+    Some(Column {
+        name: name.to_string(),
+        variable: parse_variable(name)?,
+        format: kind,
+        left,
+        width,
+        right,
+    })
+}
+
+/// Parse the variable names of the CPU emulator and of `Computer.hdl`.
+fn parse_variable(name: &str) -> Option<Variable> {
+    let variable = match name {
+        "A" | "ARegister[]" | "ARegister[0]" => Variable::A,
+        "D" | "DRegister[]" | "DRegister[0]" => Variable::D,
+        "PC" | "PC[]" => Variable::Pc,
+        "reset" => Variable::Reset,
+        "time" => Variable::Time,
+        "Keyboard[]" => Variable::Memory(KBD),
+        _ => {
+            let (memory, index) = name.strip_suffix(']')?.split_once('[')?;
+            let index: u16 = index.parse().ok()?;
+            let (base, size) = match memory {
+                "RAM" => (0, KBD + 1),
+                "RAM16K" => (0, SCREEN),
+                "Screen" => (SCREEN, KBD - SCREEN),
+                _ => return None,
+            };
+            if size <= index {
+                return None;
+            }
+            Variable::Memory(base + index)
+        }
+    };
+
+    Some(variable)
+}
+
+/// Parse the value in decimal, or with the prefix `%B`, `%D` or `%X`.
+pub(crate) fn parse_value(s: &str) -> Option<u16> {
+    let (radix, digits) = match s.get(..2) {
+        Some("%B") => (2, &s[2..]),
+        Some("%D") => (10, &s[2..]),
+        Some("%X") => (16, &s[2..]),
+        _ => (10, s),
+    };
+
+    if radix == 10 {
+        digits
+            .parse::<i32>()
+            .ok()
+            .filter(|n| (i16::MIN as i32..=u16::MAX as i32).contains(n))
+            .map(|n| n as u16)
+    } else {
+        u16::from_str_radix(digits, radix).ok()
+    }
+}
+
+/// Split the script into words, strings and the punctuations `,;!{}`, skipping comments.
+fn tokenize(src: &str) -> Result<Vec<Token>, EmulatorError> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            _ if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                let begin = line;
+                chars.next();
+                let mut prev = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if prev == '*' => break,
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            prev = c;
+                        }
+                        None => return Err(invalid(begin, "unterminated comment")),
+                    }
+                }
+            }
+            '"' => {
+                let begin = line;
+                let mut s = String::from('"');
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            s.push(c);
+                        }
+                        None => return Err(invalid(begin, "unterminated string")),
+                    }
+                }
+                tokens.push((begin, s));
+            }
+            ',' | ';' | '!' | '{' | '}' => tokens.push((line, c.to_string())),
+            _ => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek().copied() {
+                    if c.is_whitespace() || ",;!{}\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push((line, word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn invalid(line: usize, message: &str) -> EmulatorError {
+    EmulatorError::InvalidScript {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let src = "// comment\n\
+                   load Mult.hack,\n\
+                   output-list RAM[0]%D2.6.2 /* block\n comment */ time%S1.4.1;\n\
+                   set RAM[2] -1;\n\
+                   repeat 2 {\n  ticktock;\n}\n\
+                   echo \"Press, a key; now\";\n\
+                   set PC 0, output;";
+        let column = |name: &str, variable, format, widths: (usize, usize, usize)| Column {
+            name: name.to_string(),
+            variable,
+            format,
+            left: widths.0,
+            width: widths.1,
+            right: widths.2,
+        };
+
+        assert_eq!(
+            vec![
+                Command::Load("Mult.hack".to_string()),
+                Command::OutputList(vec![
+                    column("RAM[0]", Variable::Memory(0), 'D', (2, 6, 2)),
+                    column("time", Variable::Time, 'S', (1, 4, 1)),
+                ]),
+                Command::Set(Variable::Memory(2), 0xFFFF),
+                Command::Repeat(2, vec![Command::TickTock]),
+                Command::Echo,
+                Command::Set(Variable::Pc, 0),
+                Command::Output,
+            ],
+            parse(src).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let line_of = |src: &str| match parse(src) {
+            Err(EmulatorError::InvalidScript { line, .. }) => line,
+            _ => panic!("{} is parsed", src),
+        };
+
+        assert_eq!(2, line_of("load A.hack;\nset RAM[32767] 1;"));
+        assert_eq!(1, line_of("repeat {\nticktock;\n}"));
+        assert_eq!(2, line_of("load A.hack;\nrepeat 3 {\nticktock;"));
+        assert_eq!(3, line_of("output;\n\nwhile A < 3 {}"));
+        assert_eq!(1, line_of("set RAM[0] 70000;"));
+        assert_eq!(1, line_of("set time 0;"));
+    }
+
+    #[test]
+    fn variable_test() {
+        assert_eq!(Some(Variable::Memory(0x4001)), parse_variable("Screen[1]"));
+        assert_eq!(Some(Variable::Memory(KBD)), parse_variable("RAM[24576]"));
+        assert_eq!(None, parse_variable("RAM16K[16384]"));
+        assert_eq!(Some(Variable::A), parse_variable("ARegister[]"));
+        assert_eq!(Some(0x1F), parse_value("%X1F"));
+        assert_eq!(Some(5), parse_value("%B101"));
+    }
+
+    #[test]
+    fn column_test() {
+        let column = parse_column("RAM[11]%D1.6.1").unwrap();
+        assert_eq!("RAM[11] ", column.header());
+        assert_eq!("     -1 ", column.cell("-1"));
+
+        let column = parse_column("ARegister[]%D1.7.1").unwrap();
+        assert_eq!("ARegister", column.header());
+
+        let column = parse_column("time%S1.4.1").unwrap();
+        assert_eq!(" time ", column.header());
+        assert_eq!(" 0+   ", column.cell("0+"));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::computer::{Computer, MEMORY_SIZE, ROM_SIZE};
+use crate::error::EmulatorError;
+use crate::script;
+use assembler::symbol_table::PREDEFINED_SYMBOLS;
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"HACKSNAP";
+const VERSION: u16 = 1;
+
+/// Write the machine state, all in big endian.
+///
+/// The state is the magic, the version, A, D, PC, the cycles, the length of ROM without the
+/// trailing zeros and its words, and the length of RAM and its words.
+pub fn write_snapshot<W: Write>(computer: &Computer, dst: &mut W) -> io::Result<()> {
+    dst.write_all(MAGIC)?;
+    dst.write_all(&VERSION.to_be_bytes())?;
+    for register in [computer.a, computer.d, computer.pc].iter() {
+        dst.write_all(&register.to_be_bytes())?;
+    }
+    dst.write_all(&computer.cycles.to_be_bytes())?;
+
+    let rom = computer.rom();
+    let rom_len = rom.iter().rposition(|word| *word != 0).map_or(0, |i| i + 1);
+    for words in [&rom[..rom_len], computer.ram()].iter() {
+        dst.write_all(&(words.len() as u32).to_be_bytes())?;
+        for word in words.iter() {
+            dst.write_all(&word.to_be_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the machine state written by `write_snapshot`.
+pub fn read_snapshot<R: Read>(src: &mut R) -> Result<Computer, EmulatorError> {
+    let mut magic = [0; 8];
+    read_exact(src, &mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a snapshot"));
+    }
+    let version = read_u16(src)?;
+    if version != VERSION {
+        return Err(invalid(&format!("unknown version {}", version)));
+    }
+
+    let (a, d, pc) = (read_u16(src)?, read_u16(src)?, read_u16(src)?);
+    let mut cycles = [0; 8];
+    read_exact(src, &mut cycles)?;
+
+    let rom = read_words(src)?;
+    let mut computer = Computer::new(&rom)?;
+    let ram = read_words(src)?;
+    if ram.len() != MEMORY_SIZE {
+        return Err(invalid(&format!("RAM has {} words", ram.len())));
+    }
+    if ROM_SIZE <= pc as usize {
+        return Err(invalid(&format!("PC {} is out of ROM", pc)));
+    }
+    if src.read(&mut [0])? != 0 {
+        return Err(invalid("extra data after RAM"));
+    }
+
+    computer.ram_mut().copy_from_slice(&ram);
+    computer.a = a;
+    computer.d = d;
+    computer.pc = pc;
+    computer.cycles = u64::from_be_bytes(cycles);
+
+    Ok(computer)
+}
+
+pub fn save<P: AsRef<Path>>(path: P, computer: &Computer) -> Result<(), EmulatorError> {
+    let mut dst = BufWriter::new(File::create(path)?);
+    write_snapshot(computer, &mut dst)?;
+    dst.flush()?;
+
+    Ok(())
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Computer, EmulatorError> {
+    read_snapshot(&mut BufReader::new(File::open(path)?))
+}
+
+/// Parse the initial RAM contents.
+///
+/// Each line is an address and the values stored from it, like `0 6 7` for RAM[0] and RAM[1].
+/// The address is a number or a predefined symbol like `SP` or `R13`, and the values are like
+/// the ones in test scripts, `-1`, `%B101` or `%X1F`. `//` starts a comment.
+pub fn read_ram(src: &str) -> Result<Vec<(u16, u16)>, EmulatorError> {
+    let mut words = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let invalid = |message: String| EmulatorError::InvalidRam {
+            line: i + 1,
+            message,
+        };
+        let line = line.split("//").next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let address = match tokens.next() {
+            Some(address) => address,
+            None => continue,
+        };
+
+        let predefined = || {
+            PREDEFINED_SYMBOLS
+                .iter()
+                .find(|(name, _)| *name == address)
+                .map(|(_, address)| *address)
+        };
+        let start = address
+            .parse::<u16>()
+            .ok()
+            .or_else(predefined)
+            .ok_or_else(|| invalid(format!("`{}` is not an address", address)))?;
+
+        let mut count = 0;
+        for (offset, token) in tokens.enumerate() {
+            let value = script::parse_value(token)
+                .ok_or_else(|| invalid(format!("`{}` is not a value", token)))?;
+            let address = start as usize + offset;
+            if MEMORY_SIZE <= address {
+                return Err(invalid(format!("{} is out of RAM", address)));
+            }
+            words.push((address as u16, value));
+            count += 1;
+        }
+        if count == 0 {
+            return Err(invalid(format!("`{}` has no values", address)));
+        }
+    }
+
+    Ok(words)
+}
+
+/// Store the RAM contents in the file into the computer.
+pub fn load_ram<P: AsRef<Path>>(path: P, computer: &mut Computer) -> Result<(), EmulatorError> {
+    for (address, value) in read_ram(&fs::read_to_string(path)?)? {
+        computer.ram_mut()[address as usize] = value;
+    }
+
+    Ok(())
+}
+
+fn read_words<R: Read>(src: &mut R) -> Result<Vec<u16>, EmulatorError> {
+    let mut len = [0; 4];
+    read_exact(src, &mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if ROM_SIZE < len {
+        return Err(invalid(&format!("{} words are too many", len)));
+    }
+
+    (0..len).map(|_| read_u16(src)).collect()
+}
+
+fn read_u16<R: Read>(src: &mut R) -> Result<u16, EmulatorError> {
+    let mut bytes = [0; 2];
+    read_exact(src, &mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+fn read_exact<R: Read>(src: &mut R, buffer: &mut [u8]) -> Result<(), EmulatorError> {
+    src.read_exact(buffer).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => invalid("the snapshot is truncated"),
+        _ => EmulatorError::Io(e),
+    })
+}
+
+fn invalid(message: &str) -> EmulatorError {
+    EmulatorError::InvalidSnapshot(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_test() {
+        let mut computer = Computer::new(&[16, 0xFDC8, 0, 0]).unwrap();
+        computer.run(2).unwrap();
+        computer.ram_mut()[0x5FFF] = 0xABCD;
+
+        let mut dst = Vec::new();
+        write_snapshot(&computer, &mut dst).unwrap();
+        // The header, A, D, PC, cycles, ROM without the zeros and RAM.
+        assert_eq!(8 + 2 + 6 + 8 + 4 + 2 * 2 + 4 + 2 * MEMORY_SIZE, dst.len());
+        assert_eq!(b"HACKSNAP\x00\x01\x00\x10\x00\x00\x00\x02", &dst[..16]);
+
+        let restored = read_snapshot(&mut dst.as_slice()).unwrap();
+        assert_eq!(computer.rom(), restored.rom());
+        assert_eq!(computer.ram(), restored.ram());
+        assert_eq!(
+            (16, 0, 2, 2),
+            (restored.a, restored.d, restored.pc, restored.cycles)
+        );
+        assert_eq!(1, restored.ram()[16]);
+
+        let error = |bytes: &[u8]| match read_snapshot(&mut &bytes[..]) {
+            Err(EmulatorError::InvalidSnapshot(message)) => message,
+            result => panic!("unexpected {:?}", result.map(|c| c.pc)),
+        };
+        assert_eq!("the snapshot is truncated", error(&dst[..dst.len() - 1]));
+        assert_eq!("not a snapshot", error(b"HACKSNAQ"));
+        let mut extra = dst.clone();
+        extra.push(0);
+        assert_eq!("extra data after RAM", error(&extra));
+    }
+
+    #[test]
+    fn read_ram_test() {
+        let src = "// Mult\n0 6 -7\n\nSCREEN %XFFFF %B11 // the first row\nKBD 65\n";
+        assert_eq!(
+            vec![
+                (0, 6),
+                (1, 0xFFF9),
+                (0x4000, 0xFFFF),
+                (0x4001, 3),
+                (0x6000, 65)
+            ],
+            read_ram(src).unwrap()
+        );
+
+        let error = |src| match read_ram(src) {
+            Err(EmulatorError::InvalidRam { line, .. }) => line,
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!(2, error("0 1\nfoo 1\n"));
+        assert_eq!(1, error("0 x\n"));
+        assert_eq!(1, error("KBD 1 2\n"));
+        assert_eq!(1, error("16\n"));
+    }
+}
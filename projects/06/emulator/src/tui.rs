@@ -0,0 +1,356 @@
+use crate::computer::{Computer, State};
+use crate::disassembly::{self, Symbols};
+use crate::error::EmulatorError;
+use crate::screen::{self, HEIGHT, WIDTH};
+use std::io;
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_millis(33);
+/// Terminals send no key release, so the key is released after no input for a while.
+const KEY_HOLD: Duration = Duration::from_millis(200);
+/// The instructions shown before and after PC.
+const DISASSEMBLY_CONTEXT: u16 = 4;
+const CTRL_C: u8 = 3;
+
+/// The characters drawing the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyphs {
+    /// 2x4 pixels per character.
+    Braille,
+    /// 1x2 pixels per character.
+    HalfBlock,
+}
+
+/// The input from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// The key code of the Hack keyboard.
+    Code(u16),
+    Quit,
+}
+
+/// Run the program in the terminal until Ctrl-C is pressed.
+///
+/// The screen is drawn at every frame after the cycles are executed, and the keys are sent to
+/// the keyboard memory map.
+pub fn run(
+    computer: &mut Computer,
+    symbols: &Symbols,
+    glyphs: Glyphs,
+    cycles_per_frame: u64,
+) -> Result<(), EmulatorError> {
+    let _terminal = RawTerminal::enter()?;
+    let keys = spawn_key_reader();
+    let mut out = io::stdout();
+    let mut previous: Vec<String> = Vec::new();
+    let mut state = State::Running;
+    let mut released_at = Instant::now();
+
+    loop {
+        let frame_start = Instant::now();
+        while let Ok(bytes) = keys.try_recv() {
+            for key in parse_keys(&bytes) {
+                match key {
+                    Key::Code(code) => {
+                        computer.set_key(code);
+                        released_at = frame_start + KEY_HOLD;
+                    }
+                    Key::Quit => return Ok(()),
+                }
+            }
+        }
+        if released_at <= frame_start {
+            computer.set_key(0);
+        }
+
+        if state == State::Running {
+            state = computer.run(cycles_per_frame)?;
+        }
+
+        let mut lines = render(computer.ram(), glyphs);
+        lines.push(String::new());
+        lines.extend(status(computer, state, symbols));
+        draw(&mut out, &lines, &previous)?;
+        previous = lines;
+
+        if let Some(rest) = FRAME.checked_sub(frame_start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+}
+
+/// Draw the screen in the characters.
+pub fn render(ram: &[u16], glyphs: Glyphs) -> Vec<String> {
+    let pixel = |x, y| screen::is_black(ram, x, y);
+    match glyphs {
+        Glyphs::Braille => (0..HEIGHT / 4)
+            .map(|row| {
+                (0..WIDTH / 2)
+                    .map(|col| {
+                        let (x, y) = (col * 2, row * 4);
+                        // The dots 1-2-3-7 are on the left, and 4-5-6-8 are on the right.
+                        let dots = [
+                            (0, 0, 0x01),
+                            (0, 1, 0x02),
+                            (0, 2, 0x04),
+                            (1, 0, 0x08),
+                            (1, 1, 0x10),
+                            (1, 2, 0x20),
+                            (0, 3, 0x40),
+                            (1, 3, 0x80),
+                        ];
+                        let bits = dots
+                            .iter()
+                            .filter(|(dx, dy, _)| pixel(x + dx, y + dy))
+                            .fold(0, |bits, (_, _, bit)| bits | bit);
+                        std::char::from_u32(0x2800 + bits).unwrap()
+                    })
+                    .collect()
+            })
+            .collect(),
+        Glyphs::HalfBlock => (0..HEIGHT / 2)
+            .map(|row| {
+                (0..WIDTH)
+                    .map(|x| match (pixel(x, row * 2), pixel(x, row * 2 + 1)) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    })
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+/// The registers and the disassembly around PC.
+fn status(computer: &Computer, state: State, symbols: &Symbols) -> Vec<String> {
+    let mut lines = vec![format!(
+        "A: {:6}  D: {:6}  PC: {:5}  KBD: {:3}  cycles: {}  {}",
+        computer.a as i16,
+        computer.d as i16,
+        computer.pc,
+        computer.ram()[crate::computer::KBD as usize],
+        computer.cycles,
+        match state {
+            State::Running => "running (Ctrl-C to quit)",
+            State::Halted => "halted (Ctrl-C to quit)",
+        }
+    )];
+
+    let rom = computer.rom();
+    let begin = computer.pc.saturating_sub(DISASSEMBLY_CONTEXT);
+    let end = (computer.pc + DISASSEMBLY_CONTEXT).min(rom.len() as u16 - 1);
+    for address in begin..=end {
+        let marker = if address == computer.pc { '>' } else { ' ' };
+        lines.push(format!(
+            "{} {}",
+            marker,
+            disassembly::listing(rom, address, symbols)
+        ));
+    }
+
+    lines
+}
+
+/// Rewrite only the lines which changed from the previous frame.
+fn draw<W: Write>(out: &mut W, lines: &[String], previous: &[String]) -> io::Result<()> {
+    for (i, line) in lines.iter().enumerate() {
+        if previous.get(i) != Some(line) {
+            write!(out, "\x1b[{};1H{}\x1b[K", i + 1, line)?;
+        }
+    }
+    out.flush()
+}
+
+/// Convert the bytes from the terminal into the key codes of the Hack keyboard.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (key, len) = match bytes[i..] {
+            [CTRL_C, ..] => (Some(Key::Quit), 1),
+            [0x1B, b'[', ..] | [0x1B, b'O', ..] => escape_sequence(&bytes[i..]),
+            [0x1B, ..] => (Some(Key::Code(140)), 1),
+            [b'\r', b'\n', ..] => (Some(Key::Code(128)), 2),
+            [b'\r', ..] | [b'\n', ..] => (Some(Key::Code(128)), 1),
+            [0x7F, ..] | [0x08, ..] => (Some(Key::Code(129)), 1),
+            [c @ 0x20..=0x7E, ..] => (Some(Key::Code(c as u16)), 1),
+            _ => (None, 1),
+        };
+        keys.extend(key);
+        i += len;
+    }
+
+    keys
+}
+
+/// Decode `ESC [ ...` or `ESC O ...` into the key and the length of the sequence.
+fn escape_sequence(bytes: &[u8]) -> (Option<Key>, usize) {
+    // The sequence ends at the first letter or `~` after the introducer.
+    let end = match bytes[2..]
+        .iter()
+        .position(|b| b.is_ascii_alphabetic() || *b == b'~')
+    {
+        Some(i) => i + 3,
+        None => return (None, bytes.len()),
+    };
+
+    let code = match &bytes[1..end] {
+        b"[A" => 131,
+        b"[B" => 133,
+        b"[C" => 132,
+        b"[D" => 130,
+        b"[H" | b"[1~" => 134,
+        b"[F" | b"[4~" => 135,
+        b"[5~" => 136,
+        b"[6~" => 137,
+        b"[2~" => 138,
+        b"[3~" => 139,
+        b"OP" => 141,
+        b"OQ" => 142,
+        b"OR" => 143,
+        b"OS" => 144,
+        b"[15~" => 145,
+        b"[17~" => 146,
+        b"[18~" => 147,
+        b"[19~" => 148,
+        b"[20~" => 149,
+        b"[21~" => 150,
+        b"[23~" => 151,
+        b"[24~" => 152,
+        _ => return (None, end),
+    };
+
+    (Some(Key::Code(code)), end)
+}
+
+/// Read stdin in a thread, since std has no non-blocking read.
+fn spawn_key_reader() -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        let stdin = io::stdin();
+        while let Ok(n) = stdin.lock().read(&mut buffer) {
+            if n == 0 || sender.send(buffer[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// The terminal in the raw mode and the alternate screen, restored when dropped.
+struct RawTerminal {
+    /// The settings saved by `stty -g`.
+    saved: String,
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        io::stdout().flush()?;
+
+        Ok(RawTerminal {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Run stty on the terminal of stdin.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "stty failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::{MEMORY_SIZE, SCREEN};
+    use crate::disassembly::tests::symbols;
+
+    #[test]
+    fn render_test() {
+        let mut ram = vec![0; MEMORY_SIZE];
+        // The pixels (0, 0), (1, 1) and (0, 3) in the first character.
+        ram[SCREEN as usize] = 0b01;
+        ram[SCREEN as usize + 32] = 0b10;
+        ram[SCREEN as usize + 96] = 0b01;
+
+        let lines = render(&ram, Glyphs::Braille);
+        assert_eq!((64, 256), (lines.len(), lines[0].chars().count()));
+        assert_eq!(Some('\u{2851}'), lines[0].chars().next());
+        assert_eq!(Some('\u{2800}'), lines[0].chars().nth(1));
+
+        let lines = render(&ram, Glyphs::HalfBlock);
+        assert_eq!((128, 512), (lines.len(), lines[0].chars().count()));
+        assert!(lines[0].starts_with("▀▄ "));
+        assert!(lines[1].starts_with("▄  "));
+    }
+
+    #[test]
+    fn parse_keys_test() {
+        assert_eq!(
+            vec![
+                Key::Code(65),
+                Key::Code(128),
+                Key::Code(129),
+                Key::Code(131)
+            ],
+            parse_keys(b"A\r\x7f\x1b[A")
+        );
+        assert_eq!(
+            vec![
+                Key::Code(140),
+                Key::Code(139),
+                Key::Code(141),
+                Key::Code(152)
+            ],
+            parse_keys(b"\x1b\x1b[3~\x1bOP\x1b[24~")
+        );
+        assert_eq!(vec![Key::Code(120), Key::Quit], parse_keys(b"x\x03"));
+        // Unknown sequences are ignored.
+        assert_eq!(vec![Key::Code(97)], parse_keys(b"\x1b[99~a"));
+    }
+
+    #[test]
+    fn status_test() {
+        // @2; D;JGT; (LOOP) @2; 0;JMP
+        let mut computer = Computer::new(&[2, 0xE301, 2, 0xEA87]).unwrap();
+        computer.pc = 2;
+        let lines = status(&computer, State::Halted, &symbols());
+
+        assert!(lines[0].starts_with("A:      0  D:      0  PC:     2"));
+        assert_eq!("      0                           @LOOP", lines[1]);
+        assert_eq!(">     2  (LOOP)                   @LOOP", lines[3]);
+        assert_eq!("      3                           0;JMP", lines[4]);
+    }
+}
@@ -0,0 +1,87 @@
+use emulator::keyboard::Schedule;
+use emulator::screen::{self, HEIGHT, WIDTH};
+use emulator::{runner, Computer};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The CPU emulator scripts and the `Computer.hdl` scripts of the course.
+const SCRIPTS: [&str; 8] = [
+    "04/mult/Mult.tst",
+    "04/fill/FillAutomatic.tst",
+    "05/ComputerAdd.tst",
+    "05/ComputerAdd-external.tst",
+    "05/ComputerMax.tst",
+    "05/ComputerMax-external.tst",
+    "05/ComputerRect.tst",
+    "05/ComputerRect-external.tst",
+];
+
+/// The scripts of the programs generated by the VM translators of 07 and 08, which are not in
+/// the repository. Translate the .vm files into the .asm files next to the scripts and run
+/// `cargo test -- --ignored`. The programs of `08/ProgramFlow` and `SimpleFunction` must not have
+/// the bootstrap code, which the translator of 08 writes for any input.
+const TRANSLATED_SCRIPTS: [&str; 11] = [
+    "07/StackArithmetic/SimpleAdd/SimpleAdd.tst",
+    "07/StackArithmetic/StackTest/StackTest.tst",
+    "07/MemoryAccess/BasicTest/BasicTest.tst",
+    "07/MemoryAccess/PointerTest/PointerTest.tst",
+    "07/MemoryAccess/StaticTest/StaticTest.tst",
+    "08/ProgramFlow/BasicLoop/BasicLoop.tst",
+    "08/ProgramFlow/FibonacciSeries/FibonacciSeries.tst",
+    "08/FunctionCalls/SimpleFunction/SimpleFunction.tst",
+    "08/FunctionCalls/NestedCall/NestedCall.tst",
+    "08/FunctionCalls/FibonacciElement/FibonacciElement.tst",
+    "08/FunctionCalls/StaticsTest/StaticsTest.tst",
+];
+
+fn projects_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")
+}
+
+fn run_scripts(scripts: &[&str]) {
+    let mut failures = Vec::new();
+    for script in scripts.iter() {
+        let path = projects_dir().join(script);
+        match runner::run_script(&path) {
+            Ok(report) if report.is_success() => {}
+            Ok(report) => {
+                let mismatches: Vec<String> =
+                    report.mismatches.iter().map(|m| m.to_string()).collect();
+                failures.push(format!("{}\n{}", script, mismatches.join("\n")));
+            }
+            Err(error) => failures.push(format!("{}: {}", script, error)),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn course_scripts_test() {
+    run_scripts(&SCRIPTS);
+}
+
+#[test]
+#[ignore = "the programs of 07 and 08 are generated by the VM translators"]
+fn translated_scripts_test() {
+    run_scripts(&TRANSLATED_SCRIPTS);
+}
+
+#[test]
+fn fill_keyboard_test() {
+    let src = fs::read_to_string(projects_dir().join("04/fill/Fill.asm")).unwrap();
+    let mut computer = Computer::new(&assembler::assemble(&src).unwrap()).unwrap();
+    let mut schedule = Schedule::parse("0 A\n3f release\n", 100_000).unwrap();
+    let black_pixels = |computer: &Computer| {
+        (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|(x, y)| screen::is_black(computer.ram(), *x, *y))
+            .count()
+    };
+
+    // A pass over the screen takes about 100K cycles.
+    schedule.run(&mut computer, 290_000).unwrap();
+    assert_eq!(WIDTH * HEIGHT, black_pixels(&computer));
+    schedule.run(&mut computer, 400_000).unwrap();
+    assert_eq!(0, black_pixels(&computer));
+}